@@ -1,4 +1,3 @@
-use core::time;
 use std::{
     sync::{Arc, Mutex},
     thread::sleep,
@@ -35,16 +34,13 @@ fn main() {
 
     let stream = device
         .build_input_stream(
-            &custom_config.into(),
+            &custom_config,
             //&default_config.into(),
             move |data: &[f32], cb_info: &cpal::InputCallbackInfo| {
                 // react to stream events and read or write stream data here.
-                match data_lock.lock() {
-                    Ok(mut info) => {
-                        info.data = data.to_vec();
-                        info.timestamp = Some(cb_info.timestamp());
-                    }
-                    Err(_) => {}
+                if let Ok(mut info) = data_lock.lock() {
+                    info.data = data.to_vec();
+                    info.timestamp = Some(cb_info.timestamp());
                 }
             },
             move |err| {
@@ -52,6 +48,7 @@ fn main() {
                 eprintln!("{err}");
                 panic!()
             },
+            None,
         )
         .unwrap();
     stream.play().unwrap();
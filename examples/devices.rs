@@ -1,6 +1,4 @@
-use std::{ops::Add, sync::mpsc::channel};
-
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::traits::{DeviceTrait, HostTrait};
 
 fn main() {
     let host = cpal::default_host();
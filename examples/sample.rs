@@ -1,5 +1,3 @@
-use std::{ops::Add, sync::mpsc::channel};
-
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 
 fn main() {
@@ -15,8 +13,8 @@ fn main() {
     let moved_counter = std::sync::Arc::clone(&counter);
     let stream = device
         .build_input_stream(
-            &custom_config.into(),
-            move |data: &[f32], inputcallback: &cpal::InputCallbackInfo| {
+            &custom_config,
+            move |_data: &[f32], _inputcallback: &cpal::InputCallbackInfo| {
                 // react to stream events and read or write stream data here.
                 // dbg!({inputcallback});
                 moved_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
@@ -26,6 +24,7 @@ fn main() {
                 eprintln!("{err}");
                 panic!()
             },
+            None,
         )
         .unwrap();
     stream.play().unwrap();
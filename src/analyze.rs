@@ -0,0 +1,274 @@
+use crate::{
+    app::Args,
+    error::{Error, Result},
+    spectrogram::{self, Spectrogram},
+    wav,
+};
+use audiolyzer::{
+    bands::{octave_bands, Band},
+    fft::*,
+};
+
+use std::path::PathBuf;
+
+use clap::Parser;
+
+#[derive(Parser)]
+pub struct AnalyzeArgs {
+    /// WAV file to analyze
+    pub file: PathBuf,
+
+    /// Fraction of each frame shared with the next one, in [0, 1)
+    #[arg(long, default_value_t = 0.5)]
+    pub overlap: f64,
+
+    /// Where to write the spectrogram image (defaults to the input path with a .png extension)
+    #[arg(long)]
+    pub image: Option<PathBuf>,
+
+    /// Print the average spectrum in 1/N octave bands instead of bin by bin, e.g. 3 for
+    /// third-octave bands
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=24))]
+    pub bands: Option<u32>,
+}
+
+pub struct Analysis {
+    pub frame_count: usize,
+    pub spectrogram: Spectrogram,
+    pub average: Vec<f64>,
+    pub max_hold: Vec<f64>,
+    pub freq_step: f64,
+    pub hop_secs: f64,
+}
+
+// runs the whole file through the engine as fast as possible, frame after overlapping frame
+pub fn analyze(args: &Args, analyze_args: &AnalyzeArgs) -> Result<Analysis> {
+    if !(0. ..1.).contains(&analyze_args.overlap) {
+        return Err(Error::Config("--overlap must be in [0, 1)".into()));
+    }
+
+    let wav = wav::read_mono(&analyze_args.file)?;
+    let fft_size = args.fft_size as usize;
+    let hop = ((fft_size as f64 * (1. - analyze_args.overlap)) as usize).max(1);
+    let padded_len = fft_size * args.zero_pad as usize;
+    let spectrum_len = padded_len / 2 + 1;
+    let frame_count = match wav.samples.len().checked_sub(fft_size) {
+        Some(rest) => rest / hop + 1,
+        None => {
+            return Err(Error::InvalidInput(format!(
+                "file is shorter than one frame of {fft_size} samples"
+            )))
+        }
+    };
+
+    // no smoothing, every frame of the spectrogram should only show its own slice of time
+    let mut fft_engine = FFTEngine::new(wav.sample_rate, spectrum_len, 0., args.window.clone());
+    fft_engine.set_transform(args.constant_q.map_or(Transform::Fft, Transform::ConstantQ));
+    fft_engine.set_zero_pad(args.zero_pad as usize);
+    fft_engine.set_correction(args.correction);
+
+    let mut spectrogram = Spectrogram::new(frame_count, spectrum_len);
+    let mut power_sum = vec![0f64; spectrum_len];
+    let mut max_hold = vec![f64::MIN; spectrum_len];
+
+    for frame in 0..frame_count {
+        let start = frame * hop;
+        fft_engine.set_src_buf(&wav.samples[start..start + fft_size]);
+        fft_engine.apply_window();
+        fft_engine.apply_fft();
+
+        let bins = fft_engine.get_bins();
+        for (i, db) in bins.iter().enumerate() {
+            power_sum[i] += 10f64.powf(db / 10.);
+            max_hold[i] = max_hold[i].max(*db);
+        }
+        spectrogram.push(frame, &bins);
+    }
+
+    let average = power_sum
+        .iter()
+        .map(|p| 10. * (p / frame_count as f64).log10())
+        .collect();
+
+    Ok(Analysis {
+        frame_count,
+        spectrogram,
+        average,
+        max_hold,
+        freq_step: f64::from(wav.sample_rate) / padded_len as f64,
+        hop_secs: hop as f64 / f64::from(wav.sample_rate),
+    })
+}
+
+// the bands are cut off at Nyquist, above which the file holds nothing
+fn average_bands(analysis: &Analysis, fraction: u32, min_freq: f64, max_freq: f64) -> Vec<Band> {
+    let nyquist = analysis.freq_step * analysis.average.len().saturating_sub(1) as f64;
+    octave_bands(
+        &analysis.average,
+        analysis.freq_step,
+        fraction,
+        min_freq,
+        max_freq.min(nyquist),
+    )
+}
+
+pub fn run(args: &Args, analyze_args: &AnalyzeArgs) -> Result<()> {
+    let analysis = analyze(args, analyze_args)?;
+
+    match analyze_args.bands {
+        Some(fraction) => {
+            println!("center_hz,low_hz,high_hz,avg_db");
+            for band in average_bands(&analysis, fraction, args.min_freq, args.max_freq) {
+                println!(
+                    "{:.1},{:.1},{:.1},{:.1}",
+                    band.center, band.low, band.high, band.level_db
+                );
+            }
+        }
+        None => {
+            println!("freq_hz,avg_db,max_db");
+            for (i, (avg, max)) in analysis
+                .average
+                .iter()
+                .zip(analysis.max_hold.iter())
+                .enumerate()
+            {
+                println!("{:.1},{:.1},{:.1}", analysis.freq_step * i as f64, avg, max);
+            }
+        }
+    }
+
+    let image = analyze_args
+        .image
+        .clone()
+        .unwrap_or_else(|| analyze_args.file.with_extension("png"));
+    spectrogram::write_png(&analysis, &image)?;
+    eprintln!(
+        "analyzed {} frames, spectrogram written to {}",
+        analysis.frame_count,
+        image.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::app::Command;
+
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    // a 1 kHz tone in a 16-bit stereo file, with the right channel silent
+    fn write_tone(name: &str, len: usize) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..len {
+            let phase = 2. * std::f64::consts::PI * 1000. * i as f64 / f64::from(SAMPLE_RATE);
+            writer.write_sample((phase.sin() * 16000.) as i16).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    fn parse(args: &[&str]) -> (Args, AnalyzeArgs) {
+        let mut args = Args::try_parse_from(args).unwrap();
+        match args.command.take() {
+            Some(Command::Analyze(analyze_args)) => (args, analyze_args),
+            None => panic!("no analyze subcommand"),
+        }
+    }
+
+    #[test]
+    fn frames_overlap_by_the_given_fraction() {
+        let path = write_tone("audiolyzer-analyze-tone.wav", 4096);
+        let file = path.to_str().unwrap();
+        let (args, analyze_args) = parse(&[
+            "audiolyzer",
+            "analyze",
+            file,
+            "--fft-size",
+            "256",
+            "--overlap",
+            "0.75",
+        ]);
+        let analysis = analyze(&args, &analyze_args).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // a hop of 64 samples
+        assert_eq!(analysis.frame_count, (4096 - 256) / 64 + 1);
+        assert_eq!(analysis.hop_secs, 64. / f64::from(SAMPLE_RATE));
+        assert_eq!(analysis.freq_step, f64::from(SAMPLE_RATE) / 256.);
+        assert_eq!(analysis.average.len(), 129);
+
+        let loudest = |levels: &[f64]| {
+            levels
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(i, _)| i as f64 * analysis.freq_step)
+        };
+        assert_eq!(loudest(&analysis.average), Some(1000.));
+        assert_eq!(loudest(&analysis.max_hold), Some(1000.));
+    }
+
+    #[test]
+    fn average_in_octave_bands() {
+        let path = write_tone("audiolyzer-analyze-bands.wav", 4096);
+        let (args, analyze_args) = parse(&[
+            "audiolyzer",
+            "analyze",
+            path.to_str().unwrap(),
+            "--fft-size",
+            "256",
+            "--bands",
+            "1",
+        ]);
+        assert_eq!(analyze_args.bands, Some(1));
+        let analysis = analyze(&args, &analyze_args).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let bands = average_bands(&analysis, 1, 20., 20000.);
+        // up to the band centered at Nyquist, 8 kHz would be above it
+        let centers: Vec<f64> = bands.iter().map(|b| b.center).collect();
+        assert_eq!(
+            centers,
+            [31.25, 62.5, 125., 250., 500., 1000., 2000., 4000.]
+        );
+        let loudest = bands
+            .iter()
+            .max_by(|a, b| a.level_db.total_cmp(&b.level_db))
+            .unwrap();
+        assert_eq!(loudest.center, 1000.);
+    }
+
+    #[test]
+    fn files_shorter_than_a_frame() {
+        let path = write_tone("audiolyzer-analyze-short.wav", 100);
+        let (args, analyze_args) = parse(&["audiolyzer", "analyze", path.to_str().unwrap()]);
+        let result = analyze(&args, &analyze_args);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn overlap_must_leave_a_hop() {
+        for overlap in ["--overlap=1", "--overlap=-0.5"] {
+            let (args, analyze_args) = parse(&["audiolyzer", "analyze", "missing.wav", overlap]);
+            assert!(
+                matches!(analyze(&args, &analyze_args), Err(Error::Config(_))),
+                "{overlap}"
+            );
+        }
+    }
+}
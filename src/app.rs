@@ -1,25 +1,55 @@
-use crate::fft::*;
-use clap::Parser;
+use crate::{
+    analyze::AnalyzeArgs,
+    capture::{
+        build_stream, find_device_idx, find_host, DeviceDetails, DeviceWatcher, StreamInfo,
+        StreamOutput,
+    },
+    config::Config,
+    curve::{self, Curve},
+    error::{Error, Result},
+    inputs::{keymap::KeyMap, InputEvent},
+    layout::{self, Pane, Preset, View},
+    perf::PerfStats,
+    pretrigger::{BandTrigger, PreTrigger},
+    recorder::{self, Recording},
+    settings::{self, Setting},
+    snapshot::{Snapshot, MAX_SNAPSHOTS},
+    theme::{self, Theme},
+    wav::RecordFormat,
+};
+use audiolyzer::{
+    display::{ColorDepth, Palette},
+    fft::*,
+    plot::Marker,
+};
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
 
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    Device, Stream, StreamConfig,
+    traits::{DeviceTrait, HostTrait},
+    Device, Host, HostId, Stream,
 };
 
-#[derive(Clone, Debug)]
-struct StreamOutput {
-    data: Vec<f32>,
-}
-
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+// settings from the config file are passed in front of the real arguments, so a flag given
+// twice has to replace the earlier value instead of being an error
+#[command(version, about, long_about = None, args_override_self = true)]
 pub struct Args {
     /// Optional name to operate on
     #[arg(long, default_value_t = 44100)]
     pub sample_rate: u32,
 
+    /// Number of input channels to request, they are mixed down to mono for analysis
+    #[arg(long, default_value_t = 1)]
+    pub channels: u16,
+
     /// Sets a custom config file
     #[arg(long, default_value_t = 22050)]
     pub bins: usize, // TODO: rename to "bands" and change to work for octave bands
@@ -28,152 +58,1131 @@ pub struct Args {
     #[arg(long, default_value_t = 0.7)]
     pub smoothing_constant: f64,
 
-    #[arg(long, default_value_t = 60)]
+    /// Frames drawn per second, and how often headless capture analyzes a block
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u8).range(1..))]
     pub fps: u8,
 
-    #[arg(long, default_value_t = 20)]
-    pub min_freq: u16,
+    /// Lower edge of the displayed frequency range in Hz
+    #[arg(long, default_value_t = 20.)]
+    pub min_freq: f64,
 
-    #[arg(long, default_value_t = 20000)]
-    pub max_freq: u16,
+    /// Upper edge of the displayed frequency range in Hz, up to half the sample rate
+    #[arg(long, default_value_t = 20000.)]
+    pub max_freq: f64,
 
     #[arg(long, default_value_t = String::from("DISCRETE"))]
     pub display_mode: String,
 
-    #[arg(long, default_value_t = 1024)]
+    /// Characters the spectrum is drawn with: braille, sextant, quadrant, halfblock, block or dot
+    #[arg(long, default_value = "braille")]
+    pub marker: Marker,
+
+    /// Pane layout: single, stacked, scope, dashboard or one defined in the config file
+    #[arg(long, default_value_t = String::from("single"))]
+    pub layout: String,
+
+    /// Samples per analyzed block, from 64 to 65536
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 1024,
+        value_parser = clap::value_parser!(u32)
+            .range(i64::from(settings::MIN_FFT_SIZE)..=i64::from(settings::MAX_FFT_SIZE))
+    )]
     pub fft_size: u32,
+
+    /// Window function every block is multiplied with: hann, hamming, blackman, nuttall,
+    /// blackman-harris, flat-top, rectangular, kaiser:<beta>, tukey:<alpha>, gaussian:<sigma>
+    /// or chebyshev:<attenuation dB>
+    #[arg(long, global = true, default_value = "blackman-harris")]
+    pub window: WindowType,
+
+    /// What levels are corrected for so they read the same with every window: none, amplitude
+    /// (tones) or noise (broadband levels per bin)
+    #[arg(long, global = true, default_value = "amplitude")]
+    pub correction: Correction,
+
+    /// Pad every block with zeros to this many times its length, which interpolates the
+    /// spectrum between bins without improving the resolution
+    #[arg(long, global = true, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub zero_pad: u32,
+
+    /// Analyze with a constant-Q transform of this many bins per octave instead of a plain FFT.
+    /// It only reaches down to where a bin's kernel fits in the block, about sample rate *
+    /// 17 / fft-size Hz at 12 bins per octave and twice that at 24
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..=96))]
+    pub constant_q: Option<u32>,
+
+    /// Audio host to capture through, e.g. alsa or jack (defaults to the platform default)
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// Name of the input device to capture from
+    #[arg(long)]
+    pub device: Option<String>,
+
+    /// Run without the terminal UI and print results to stdout
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Analyze a WAV file instead of capturing live input (headless only)
+    #[arg(long, requires = "headless")]
+    pub input_file: Option<PathBuf>,
+
+    /// Stop after this many seconds (required for headless capture)
+    #[arg(long, requires = "headless")]
+    pub duration: Option<f64>,
+
+    /// Print one line per analyzed frame instead of only a summary
+    #[arg(long, requires = "headless")]
+    pub per_frame: bool,
+
+    /// Start recording the captured input, mixed down to mono, to this WAV file right away
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+
+    /// Sample format of recorded WAV files
+    #[arg(long, value_enum, default_value_t = RecordFormat::F32)]
+    pub record_format: RecordFormat,
+
+    /// Seconds of input kept in memory before a trigger, 0 turns pre-trigger capture off
+    #[arg(long, default_value_t = 0., value_parser = parse_secs)]
+    pub pretrigger: f64,
+
+    /// Seconds of input saved after a trigger fired
+    #[arg(long, default_value_t = 2., value_parser = parse_secs)]
+    pub posttrigger: f64,
+
+    /// Trigger a dump when the energy in the trigger band rises above this level (needs
+    /// --pretrigger)
+    #[arg(long, allow_negative_numbers = true, requires = "pretrigger")]
+    pub trigger_db: Option<f64>,
+
+    /// Lower edge of the band watched by --trigger-db
+    #[arg(long, default_value_t = 20.)]
+    pub trigger_min_freq: f64,
+
+    /// Upper edge of the band watched by --trigger-db
+    #[arg(long, default_value_t = 20000.)]
+    pub trigger_max_freq: f64,
+
+    /// Reference curve to draw, a CSV of freq_hz,db lines or a JSON list of points (repeatable)
+    #[arg(long)]
+    pub curve: Vec<PathBuf>,
+
+    /// Curve the spectrum must stay below, in the same formats as --curve (repeatable)
+    #[arg(long)]
+    pub upper_mask: Vec<PathBuf>,
+
+    /// Curve the spectrum must stay above, in the same formats as --curve (repeatable)
+    #[arg(long)]
+    pub lower_mask: Vec<PathBuf>,
+
+    /// Color theme: default, heat, rainbow, mono or one defined in the config file
+    #[arg(long, default_value_t = String::from("default"))]
+    pub theme: String,
+
+    /// Colors the terminal supports: truecolor, 256 or 16 (detected from COLORTERM and TERM)
+    #[arg(long)]
+    pub colors: Option<ColorDepth>,
+
+    /// Config file to read defaults from (defaults to audiolyzer/config.toml in the user's
+    /// config directory)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Named profile from the config file whose settings override the top-level ones
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Append log messages to this file, nothing is logged without it
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Most detailed messages to log: error, warn, info, debug or trace
+    #[arg(long, global = true, default_value_t = LevelFilter::Info)]
+    pub log_level: LevelFilter,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// a length of time in seconds, which can't be negative or infinite
+fn parse_secs(value: &str) -> std::result::Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0. => Ok(secs),
+        Ok(_) => Err("expected a finite number of seconds, 0 or more".into()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Analyze a whole WAV file offline and render its spectrogram
+    Analyze(AnalyzeArgs),
 }
 
 pub struct App {
     pub edit_in_device: bool,
+    // while the device picker is open, whether the host row or the device list has focus
+    pub edit_host: bool,
+    pub hosts: Vec<HostId>,
+    pub hosts_idx: usize,
+    pub host: Host,
     pub in_devices: Vec<Device>,
     pub in_devices_idx: usize,
+    // typed while the device list of the picker has focus, only matching devices are listed
+    pub device_filter: String,
+    // of the device selected in the picker, together with its index
+    pub device_details: Option<(usize, DeviceDetails)>,
+    // the device to capture from, matched by name so it can be found again after a reconnect
+    pub device_name: String,
+    pub device_lost: bool,
+    device_watcher: DeviceWatcher,
     pub audio_lock: Arc<Mutex<StreamOutput>>,
     pub freq_step: f64,
     pub fft_engine: FFTEngine,
-    pub stream: Stream,
+    pub stream: Option<Stream>,
+    // the config the device actually runs at, which can differ from the requested one
+    pub stream_info: StreamInfo,
+    // shown at the bottom of the canvas, e.g. when the stream failed and is being retried
+    pub status: Option<String>,
+    pub retry_at: Option<Instant>,
+    pub recording: Option<Recording>,
+    pub pretrigger: PreTrigger,
+    pub last_dump: Option<PathBuf>,
     pub args: Args,
+    pub config: Config,
+    pub keymap: KeyMap,
+    pub show_help: bool,
+    // how many bindings the help is scrolled down by
+    pub help_scroll: usize,
+    pub show_settings: bool,
+    pub settings_idx: usize,
+    pub perf: PerfStats,
+    pub show_perf: bool,
+    // the zoomed and panned frequency range on screen, --min-freq and --max-freq by default
+    pub freq_view: (f64, f64),
+    // the frequency under the mouse, zooming with the keyboard centers on it as well
+    pub cursor_freq: Option<f64>,
+    // the spectrum held on screen while frozen, analysis and capture keep running behind it
+    pub frozen: Option<Snapshot>,
+    pub snapshots: Vec<Snapshot>,
+    // the snapshot the difference mode compares against
+    pub reference_idx: usize,
+    pub show_difference: bool,
+    // reference curves and masks from --curve, --upper-mask and --lower-mask
+    pub curves: Vec<Curve>,
+    pub themes: Vec<Theme>,
+    pub theme_idx: usize,
+    pub color_depth: ColorDepth,
+    pub presets: Vec<Preset>,
+    pub preset_idx: usize,
+    // one per view of the current preset
+    pub panes: Vec<Pane>,
+    // the pane the display mode and marker keys apply to
+    pub focus: usize,
+    // the latest spectra for the waterfall, newest first, only kept while one is shown
+    pub waterfall: VecDeque<Snapshot>,
+    // the block of input the spectrum was computed from, for the scope and meters
+    pub samples: Vec<f32>,
 }
 
 impl App {
-    pub fn new() -> App {
-        let args = Args::parse();
-        let SAMPLE_RATE: u32 = args.sample_rate;
-        let BINS: usize = args.bins;
-        let S: f64 = args.smoothing_constant;
-        let FPS: u8 = args.fps;
-        let MIN_FREQ: u16 = args.min_freq;
-        let MAX_FREQ: u16 = args.max_freq;
-        let DISPLAY_MODE: &str = args.display_mode.as_str();
-        let fft_size = args.fft_size;
-        let freq_step = f64::from(SAMPLE_RATE) / f64::from(fft_size);
-
-        let data_lock = Arc::new(Mutex::new(StreamOutput { data: vec![] }));
-        let main_data_lock = data_lock.clone();
+    pub fn new(args: Args, config: Config) -> Result<App> {
+        let keymap = KeyMap::new(&config)?;
+        let curves = curve::load_all(&args)?;
+        let color_depth = args.colors.unwrap_or_else(ColorDepth::detect);
+        let themes = theme::load(&config, color_depth)?;
+        let theme_idx = themes
+            .iter()
+            .position(|t| t.name == args.theme)
+            .ok_or_else(|| Error::Config(format!("unknown theme: {}", args.theme)))?;
+        let presets = layout::load(&config)?;
+        let preset_idx = presets
+            .iter()
+            .position(|p| p.name == args.layout)
+            .ok_or_else(|| Error::Config(format!("unknown layout: {}", args.layout)))?;
+
+        let pretrigger = PreTrigger::new(
+            Duration::from_secs_f64(args.pretrigger),
+            Duration::from_secs_f64(args.posttrigger),
+            frame_interval(args.fps),
+            args.trigger_db.map(|level_db| BandTrigger {
+                level_db,
+                min_freq: args.trigger_min_freq,
+                max_freq: args.trigger_max_freq,
+            }),
+        );
+
+        let data_lock = Arc::new(Mutex::new(StreamOutput::default()));
 
         let edit_in_device = false;
-        let in_devices: Vec<Device> = cpal::default_host().input_devices().unwrap().collect();
+        let host = find_host(args.host.as_deref())?;
+        let hosts = cpal::available_hosts();
+        let hosts_idx = hosts.iter().position(|id| *id == host.id()).unwrap_or(0);
+        let in_devices: Vec<Device> = host.input_devices()?.collect();
 
-        let (in_devices_idx, device) = in_devices
-            .iter()
-            .enumerate()
-            .find(|(i, possible_device)| possible_device.name().unwrap() == "BlackHole 2ch")
-            .unwrap();
-
-        let custom_config = cpal::StreamConfig {
-            channels: 1,
-            sample_rate: cpal::SampleRate(SAMPLE_RATE), // default sample rate 44100
-            buffer_size: cpal::BufferSize::Fixed(fft_size), // default buffer size cpal::BufferSize::Default
-        };
+        let in_devices_idx = find_device_idx(&host, &in_devices, args.device.as_deref())
+            .ok_or_else(|| {
+                Error::DeviceNotFound(args.device.clone().unwrap_or_else(|| "default".into()))
+            })?;
 
-        let stream = device
-            .build_input_stream(
-                &custom_config,
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    // react to stream events and read or write stream data here.
-                    match data_lock.lock() {
-                        Ok(mut streamoutput) => streamoutput.data = data.to_vec(),
-                        _ => (),
-                    }
-                },
-                move |err| {
-                    // react to errors here.
-                    eprintln!("{err}");
-                    panic!()
-                },
-                None,
-            )
-            .unwrap();
-        stream.play().unwrap();
+        let device_name = in_devices[in_devices_idx].name().unwrap_or_default();
+        let (stream, stream_info) = build_stream(
+            &in_devices[in_devices_idx],
+            args.sample_rate,
+            args.channels,
+            args.fft_size,
+            data_lock.clone(),
+        )?;
+        if let Ok(mut res) = data_lock.lock() {
+            res.history_len = history_len(&pretrigger, stream_info.sample_rate);
+        }
+        let freq_step = f64::from(stream_info.sample_rate) / padded_len(&args) as f64;
 
-        let fft_engine = FFTEngine::new(SAMPLE_RATE, BINS, S, WindowType::Blackman);
+        let mut fft_engine = FFTEngine::new(
+            stream_info.sample_rate,
+            spectrum_bins(&args),
+            args.smoothing_constant,
+            args.window.clone(),
+        );
+        fft_engine.set_correction(args.correction);
+        fft_engine.set_transform(args.constant_q.map_or(Transform::Fft, Transform::ConstantQ));
+        fft_engine.set_zero_pad(args.zero_pad as usize);
 
-        App {
+        let mut app = App {
             edit_in_device,
+            edit_host: false,
+            hosts,
+            hosts_idx,
+            device_watcher: DeviceWatcher::spawn(host.id(), RETRY_INTERVAL),
+            host,
             fft_engine,
             in_devices,
             in_devices_idx,
-            audio_lock: main_data_lock,
+            device_filter: String::new(),
+            device_details: None,
+            device_name,
+            device_lost: false,
+            audio_lock: data_lock,
             freq_step,
             args,
-            stream,
+            stream: Some(stream),
+            stream_info,
+            status: None,
+            retry_at: None,
+            recording: None,
+            pretrigger,
+            last_dump: None,
+            config,
+            keymap,
+            show_help: false,
+            help_scroll: 0,
+            show_settings: false,
+            settings_idx: 0,
+            perf: PerfStats::default(),
+            show_perf: false,
+            freq_view: (0., 0.),
+            cursor_freq: None,
+            frozen: None,
+            snapshots: vec![],
+            reference_idx: 0,
+            show_difference: false,
+            curves,
+            themes,
+            theme_idx,
+            color_depth,
+            presets,
+            preset_idx,
+            panes: vec![],
+            focus: 0,
+            waterfall: VecDeque::new(),
+            samples: vec![],
+        };
+        app.reset_view();
+        app.apply_preset();
+
+        if let Some(path) = app.args.record.clone() {
+            app.start_recording(&path)?;
+        }
+
+        Ok(app)
+    }
+
+    // the event loop gets an `InputEvent::AudioFrame` through `frame_tx` whenever the stream
+    // delivered new samples
+    pub fn notify_frames(&mut self, frame_tx: Sender<InputEvent>) {
+        if let Ok(mut res) = self.audio_lock.lock() {
+            res.frame_tx = Some(frame_tx);
         }
     }
 
     pub fn update_state(&mut self) {
+        self.check_stream();
+
         let data = match self.audio_lock.lock() {
-            Ok(res) => res.data.clone(),
+            Ok(mut res) => {
+                for interval in res.callback_intervals.drain(..) {
+                    self.perf.callback.push(interval);
+                }
+                self.perf.xruns.catch_up(&res.xruns);
+                res.frame_pending = false;
+                self.perf.buffer_fill = res.data.len() as f64 / res.frame_len.max(1) as f64;
+                // until a new stream filled a whole block the bins wouldn't be `freq_step`
+                // apart, so nothing is analyzed before that
+                if res.data.len() < res.frame_len {
+                    return;
+                }
+                res.data.clone()
+            }
             _ => return,
         };
 
+        let started = Instant::now();
         self.fft_engine.set_src_buf(&data);
 
         self.fft_engine.apply_window();
         self.fft_engine.apply_fft();
+        self.perf.fft.push(started.elapsed());
+
+        if !data.is_empty() {
+            let bins = self.fft_engine.get_bins();
+            let spectrum_len = (padded_len(&self.args) / 2 + 1).min(bins.len());
+            self.pretrigger
+                .push_frame(&bins[..spectrum_len], self.freq_step);
+            if self.frozen.is_none() && self.panes.iter().any(|p| p.view == View::Waterfall) {
+                self.waterfall.truncate(WATERFALL_ROWS - 1);
+                self.waterfall.push_front(Snapshot {
+                    bins: bins[..spectrum_len].to_vec(),
+                    freq_step: self.freq_step,
+                });
+            }
+        }
+        if self.pretrigger.is_pending() {
+            if let Ok(res) = self.audio_lock.lock() {
+                if let Some(path) =
+                    self.pretrigger
+                        .poll(&res.history, self.stream_info.sample_rate, self.freq_step)
+                {
+                    self.last_dump = Some(path);
+                }
+            }
+        }
+        self.samples = data;
+    }
+
+    // the spectrum to draw and its bin spacing, which is the frozen one while frozen
+    // only the first len / 2 + 1 bins of the padded block hold output, the engine's bins above
+    // them are placeholders that masks and snapshots must not see
+    pub fn display_spectrum(&self) -> Snapshot {
+        self.frozen.clone().unwrap_or_else(|| {
+            let mut bins = self.fft_engine.get_bins();
+            bins.truncate(padded_len(&self.args) / 2 + 1);
+            Snapshot {
+                bins,
+                freq_step: self.freq_step,
+            }
+        })
+    }
+
+    pub fn toggle_freeze(&mut self) {
+        self.frozen = match self.frozen {
+            Some(_) => None,
+            None => Some(self.display_spectrum()),
+        };
+    }
+
+    pub fn store_snapshot(&mut self) {
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.remove(0);
+        }
+        self.snapshots.push(self.display_spectrum());
+        self.reference_idx = self.reference_idx.min(self.snapshots.len() - 1);
+    }
+
+    pub fn clear_snapshots(&mut self) {
+        self.snapshots.clear();
+        self.reference_idx = 0;
+        self.show_difference = false;
+    }
+
+    pub fn next_reference(&mut self) {
+        if !self.snapshots.is_empty() {
+            self.reference_idx = (self.reference_idx + 1) % self.snapshots.len();
+        }
+    }
+
+    pub fn toggle_difference(&mut self) {
+        if self.snapshots.is_empty() {
+            self.status = Some("store a snapshot to compare against first".into());
+            return;
+        }
+        self.show_difference = !self.show_difference;
+    }
+
+    pub fn reference(&self) -> Option<&Snapshot> {
+        self.snapshots.get(self.reference_idx)
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.themes[self.theme_idx]
+    }
+
+    pub fn next_theme(&mut self) {
+        self.theme_idx = (self.theme_idx + 1) % self.themes.len();
     }
 
+    pub fn palette(&self) -> Palette {
+        Palette {
+            coloring: self.theme().spectrum.clone(),
+            depth: self.color_depth,
+        }
+    }
+
+    // builds the panes of the current preset, spectrum panes start out with --display-mode and
+    // --marker
+    pub fn apply_preset(&mut self) {
+        self.panes = self.presets[self.preset_idx]
+            .views()
+            .into_iter()
+            .map(|view| Pane {
+                view,
+                display_mode: match view {
+                    View::Scope => "LINE".into(),
+                    _ => self.args.display_mode.clone(),
+                },
+                marker: self.args.marker,
+            })
+            .collect();
+        self.focus = 0;
+        if !self.panes.iter().any(|p| p.view == View::Waterfall) {
+            self.waterfall.clear();
+        }
+    }
+
+    pub fn next_preset(&mut self) {
+        self.preset_idx = (self.preset_idx + 1) % self.presets.len();
+        self.apply_preset();
+        self.status = Some(format!("layout: {}", self.presets[self.preset_idx].name));
+    }
+
+    pub fn next_pane(&mut self) {
+        self.focus = (self.focus + 1) % self.panes.len();
+    }
+
+    // from the noise floor to a loud tone as read with the default amplitude correction, which
+    // lifts tones about 9 dB above the window's attenuated level
     pub fn normalize_db(&self, value: f64) -> f64 {
-        let max_val = -25f64;
-        let min_val = -85f64;
+        let max_val = -16f64;
+        let min_val = -76f64;
 
         let normal_val = (value - min_val) / (max_val - min_val);
 
-        if normal_val < 0. {
-            0.
-        } else if normal_val > 1. {
-            1.
+        normal_val.clamp(0., 1.)
+    }
+
+    // a failed stream is dropped and rebuilt every few seconds until it works again, and a
+    // device that disappeared from the device list is reconnected once it shows up again
+    pub fn check_stream(&mut self) {
+        let error = self
+            .audio_lock
+            .lock()
+            .ok()
+            .and_then(|mut res| res.error.take());
+        if let Some(error) = error {
+            self.stream = None;
+            self.schedule_retry(format!("stream error: {error}"));
+        }
+
+        if let Some(names) = self.device_watcher.latest() {
+            let present = names.contains(&self.device_name);
+            if !present && !self.device_lost {
+                self.device_lost();
+            } else if present && self.device_lost {
+                self.reconnect();
+            }
+        }
+
+        if self
+            .retry_at
+            .is_some_and(|retry_at| Instant::now() >= retry_at)
+        {
+            self.retry_at = None;
+            self.reconnect();
+        }
+    }
+
+    fn device_lost(&mut self) {
+        log::warn!("device lost: {}", self.device_name);
+        self.stream = None;
+        self.device_lost = true;
+        self.retry_at = None;
+        self.status = Some(format!(
+            "device lost: {} - waiting for it to reconnect",
+            self.device_name
+        ));
+    }
+
+    // looks the device up by name again, the old handle may point to a device that is gone
+    fn reconnect(&mut self) {
+        let in_devices: Vec<Device> = match self.host.input_devices() {
+            Ok(devices) => devices.collect(),
+            Err(err) => return self.schedule_retry(err.to_string()),
+        };
+        let Some(idx) = find_device_idx(&self.host, &in_devices, Some(&self.device_name)) else {
+            return self.device_lost();
+        };
+
+        self.in_devices = in_devices;
+        self.in_devices_idx = idx;
+        if let Err(err) = self.update_input_device() {
+            self.schedule_retry(err.to_string());
+        }
+    }
+
+    fn schedule_retry(&mut self, message: String) {
+        log::warn!("{message}, retrying in {}s", RETRY_INTERVAL.as_secs());
+        self.status = Some(format!(
+            "{message} - retrying in {}s",
+            RETRY_INTERVAL.as_secs()
+        ));
+        self.retry_at = Some(Instant::now() + RETRY_INTERVAL);
+    }
+
+    // selects the running device again, or the host's default one once it was unplugged
+    pub fn refresh_devices(&mut self) -> Result<()> {
+        self.in_devices = self.host.input_devices()?.collect();
+        self.device_details = None;
+        if self.in_devices.is_empty() {
+            self.in_devices_idx = 0;
+            self.status = Some(format!("{} has no input devices", self.host.id().name()));
+            return Ok(());
+        }
+        self.in_devices_idx =
+            find_device_idx(&self.host, &self.in_devices, Some(&self.device_name))
+                .or_else(|| find_device_idx(&self.host, &self.in_devices, None))
+                .unwrap_or(0);
+        Ok(())
+    }
+
+    pub fn open_picker(&mut self) -> Result<()> {
+        self.refresh_devices()?;
+        self.edit_in_device = true;
+        self.edit_host = false;
+        self.show_settings = false;
+        self.device_filter.clear();
+        self.update_device_details();
+        Ok(())
+    }
+
+    // closes the device picker or the settings panel, or else the performance overlay, and
+    // returns whether one was open
+    pub fn close_overlay(&mut self) -> bool {
+        if self.edit_in_device || self.show_settings {
+            self.edit_in_device = false;
+            self.edit_host = false;
+            self.show_settings = false;
+        } else if self.show_perf {
+            self.show_perf = false;
         } else {
-            normal_val
+            return false;
+        }
+        true
+    }
+
+    // indices into `in_devices` of the devices whose name contains the filter, ignoring case
+    pub fn visible_devices(&self) -> Vec<usize> {
+        matching_names(
+            self.in_devices.iter().map(|d| d.name().unwrap_or_default()),
+            &self.device_filter,
+        )
+    }
+
+    pub fn step_device(&mut self, offset: i32) {
+        if let Some(idx) = step_within(&self.visible_devices(), self.in_devices_idx, offset) {
+            self.in_devices_idx = idx;
+            self.update_device_details();
         }
     }
 
-    pub fn update_input_device(&mut self) {
-        let custom_config = cpal::StreamConfig {
-            channels: 1,
-            sample_rate: cpal::SampleRate(self.args.sample_rate),
-            buffer_size: cpal::BufferSize::Fixed(self.args.fft_size),
+    // keeps the selection if it still matches, otherwise selects the first device that does
+    pub fn set_device_filter(&mut self, filter: String) {
+        self.device_filter = filter;
+        let visible = self.visible_devices();
+        if !visible.contains(&self.in_devices_idx) {
+            if let Some(first) = visible.first() {
+                self.in_devices_idx = *first;
+            }
+        }
+        self.update_device_details();
+    }
+
+    fn update_device_details(&mut self) {
+        if self.device_details.as_ref().map(|(idx, _)| *idx) == Some(self.in_devices_idx) {
+            return;
+        }
+        self.device_details = self
+            .in_devices
+            .get(self.in_devices_idx)
+            .map(|device| (self.in_devices_idx, DeviceDetails::query(device)));
+    }
+
+    // switches to the host selected in the picker, the device is only changed once one of
+    // the new host's devices is picked
+    pub fn update_host(&mut self) -> Result<()> {
+        let host = cpal::host_from_id(self.hosts[self.hosts_idx])?;
+        let in_devices: Vec<Device> = host.input_devices()?.collect();
+        // an index into an empty list is never used, every access goes through `get` or
+        // `visible_devices`
+        self.in_devices_idx = find_device_idx(&host, &in_devices, None).unwrap_or(0);
+        self.in_devices = in_devices;
+        self.host = host;
+        self.device_filter.clear();
+        self.device_details = None;
+        self.update_device_details();
+        log::info!("switched to host {}", self.host.id().name());
+        if self.in_devices.is_empty() {
+            self.status = Some(format!("{} has no input devices", self.host.id().name()));
+        }
+        Ok(())
+    }
+
+    // the host the running stream was opened through, which differs from `host` while a host
+    // has been picked but none of its devices yet
+    pub fn stream_host(&self) -> HostId {
+        self.device_watcher.host_id
+    }
+
+    pub fn update_input_device(&mut self) -> Result<()> {
+        let Some(device) = self.in_devices.get(self.in_devices_idx).cloned() else {
+            return Err(Error::DeviceNotFound(format!(
+                "{} has no input devices",
+                self.host.id().name()
+            )));
         };
 
-        self.audio_lock = Arc::new(Mutex::new(StreamOutput { data: vec![] }));
-        let data_lock = self.audio_lock.clone();
-        self.stream = self.in_devices[self.in_devices_idx]
-            .build_input_stream(
-                &custom_config,
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    // react to stream events and read or write stream data here.
-                    match data_lock.lock() {
-                        Ok(mut streamoutput) => streamoutput.data = data.to_vec(),
-                        _ => (),
-                    }
-                },
-                move |err| {
-                    // react to errors here.
-                    eprintln!("{err}");
-                    panic!()
-                },
-                None,
-            )
-            .unwrap();
-        self.stream.play().unwrap();
+        // keep the event loop informed, an ongoing recording is only handed over once the
+        // new stream's rate is known
+        let (record_tx, frame_tx) = self
+            .audio_lock
+            .lock()
+            .map(|mut res| (res.record_tx.take(), res.frame_tx.take()))
+            .unwrap_or_default();
+        self.audio_lock = Arc::new(Mutex::new(StreamOutput {
+            frame_tx,
+            ..Default::default()
+        }));
+        // drop the old stream first, some hosts refuse to open a device twice
+        self.stream = None;
+        self.device_name = device.name().unwrap_or_default();
+        log::info!("switching to device {}", self.device_name);
+        if self.device_watcher.host_id != self.host.id() {
+            self.device_watcher = DeviceWatcher::spawn(self.host.id(), RETRY_INTERVAL);
+        }
+        let (stream, stream_info) = match build_stream(
+            &device,
+            self.args.sample_rate,
+            self.args.channels,
+            self.args.fft_size,
+            self.audio_lock.clone(),
+        ) {
+            Ok(built) => built,
+            Err(err) => {
+                // the recording goes on once a retry gets the stream running again
+                if let Ok(mut res) = self.audio_lock.lock() {
+                    res.record_tx = record_tx;
+                }
+                return Err(err);
+            }
+        };
+        if let Ok(mut res) = self.audio_lock.lock() {
+            res.history_len = history_len(&self.pretrigger, stream_info.sample_rate);
+        }
+        // the new device may run at a different rate, which moves every bin
+        self.freq_step = f64::from(stream_info.sample_rate) / padded_len(&self.args) as f64;
+        self.fft_engine.set_sample_rate(stream_info.sample_rate);
+        self.fft_engine.reset(spectrum_bins(&self.args));
+        self.stream = Some(stream);
+        self.stream_info = stream_info;
+        let (low, high) = self.freq_view;
+        self.set_view(low, high);
+        self.status = None;
+        self.device_lost = false;
+
+        let same_rate = self
+            .recording
+            .as_ref()
+            .is_some_and(|recording| recording.sample_rate == self.stream_info.sample_rate);
+        if let Some(record_tx) = record_tx {
+            if same_rate {
+                if let Ok(mut res) = self.audio_lock.lock() {
+                    res.record_tx = Some(record_tx);
+                }
+            } else if let Err(err) = self.split_recording() {
+                log::warn!("recording stopped: {err}");
+                self.status = Some(format!("recording stopped: {err}"));
+            }
+        }
+        Ok(())
+    }
+
+    // a WAV file has a single sample rate, so once the stream runs at another one the
+    // recording goes on in a new file next to the old one
+    fn split_recording(&mut self) -> Result<()> {
+        let Some(path) = self.recording.as_ref().map(|r| r.path.clone()) else {
+            return Ok(());
+        };
+        self.stop_recording()?;
+        let prefix = path.with_extension("");
+        let next = recorder::timestamped_path(&prefix.to_string_lossy());
+        self.start_recording(&next)?;
+        self.status = Some(format!(
+            "sample rate changed to {} Hz, recording continues in {}",
+            self.stream_info.sample_rate,
+            next.display()
+        ));
+        Ok(())
+    }
+
+    pub fn start_recording(&mut self, path: &Path) -> Result<()> {
+        let (recording, record_tx) =
+            Recording::start(path, self.args.record_format, self.stream_info.sample_rate)?;
+        if let Ok(mut res) = self.audio_lock.lock() {
+            res.record_tx = Some(record_tx);
+        }
+        log::info!("recording to {}", path.display());
+        self.recording = Some(recording);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Result<()> {
+        if let Ok(mut res) = self.audio_lock.lock() {
+            res.record_tx = None;
+        }
+        match self.recording.take() {
+            Some(recording) => {
+                log::info!("recording to {} stopped", recording.path.display());
+                recording.finish()
+            }
+            None => Ok(()),
+        }
+    }
+
+    // writes what can be changed at runtime back to the config file, into the active profile
+    // if there is one
+    pub fn save_config(&mut self) -> Result<()> {
+        let settings = [
+            ("window", self.args.window.to_string().into()),
+            ("display_mode", self.args.display_mode.as_str().into()),
+            ("marker", self.args.marker.name().into()),
+            ("layout", self.presets[self.preset_idx].name.as_str().into()),
+            ("device", self.device_name.as_str().into()),
+            ("host", self.host.id().name().into()),
+        ];
+        self.config.save(&settings)?;
+        log::info!("settings saved to {}", self.config.path.display());
+        self.status = Some(format!("settings saved to {}", self.config.path.display()));
+        Ok(())
+    }
+
+    pub fn setting_value(&self, setting: Setting) -> String {
+        match setting {
+            Setting::Window => self.args.window.to_string(),
+            Setting::FftSize => self.args.fft_size.to_string(),
+            Setting::SampleRate => format!(
+                "{} Hz (running at {} Hz)",
+                self.args.sample_rate, self.stream_info.sample_rate
+            ),
+            Setting::Smoothing => format!("{:.2}", self.args.smoothing_constant),
+            Setting::ZeroPad => format!("{}x", self.args.zero_pad),
+            Setting::ConstantQ => match self.args.constant_q {
+                Some(bins_per_octave) => format!("{bins_per_octave} bins per octave"),
+                None => "off".into(),
+            },
+            Setting::DisplayMode | Setting::Marker => {
+                let pane = &self.panes[self.focus];
+                match (pane.view.has_display_mode(), setting) {
+                    (false, _) => format!("- ({} pane)", pane.view.name()),
+                    (true, Setting::DisplayMode) => pane.display_mode.clone(),
+                    (true, _) => pane.marker.name().into(),
+                }
+            }
+            Setting::MinFreq => format!("{} Hz", self.args.min_freq),
+            Setting::MaxFreq => format!("{} Hz", self.args.max_freq),
+            Setting::Fps => self.args.fps.to_string(),
+        }
+    }
+
+    // `direction` is 1 to increase and -1 to decrease the setting
+    pub fn adjust_setting(&mut self, setting: Setting, direction: i32) {
+        log::debug!(
+            "{} {}",
+            setting.label(),
+            if direction > 0 { "up" } else { "down" }
+        );
+        match setting {
+            Setting::Window => {
+                self.args.window = settings::step_window(&self.args.window, direction);
+                self.fft_engine.set_window(self.args.window.clone());
+            }
+            Setting::FftSize => {
+                self.args.fft_size = settings::step_fft_size(self.args.fft_size, direction);
+                self.restart_stream();
+            }
+            Setting::SampleRate => {
+                self.args.sample_rate =
+                    settings::step_sample_rate(self.args.sample_rate, direction);
+                self.restart_stream();
+            }
+            Setting::Smoothing => {
+                self.args.smoothing_constant =
+                    settings::step_smoothing(self.args.smoothing_constant, direction);
+                self.fft_engine.set_smoothing(self.args.smoothing_constant);
+            }
+            // the block stays the same, only the bins it is spread over change
+            Setting::ZeroPad => {
+                self.args.zero_pad = settings::step_zero_pad(self.args.zero_pad, direction);
+                self.fft_engine.set_zero_pad(self.args.zero_pad as usize);
+                self.fft_engine.reset(spectrum_bins(&self.args));
+                self.freq_step =
+                    f64::from(self.stream_info.sample_rate) / padded_len(&self.args) as f64;
+                self.waterfall.clear();
+            }
+            Setting::ConstantQ => {
+                self.args.constant_q = settings::step_constant_q(self.args.constant_q, direction);
+                self.fft_engine.set_transform(
+                    self.args
+                        .constant_q
+                        .map_or(Transform::Fft, Transform::ConstantQ),
+                );
+            }
+            Setting::DisplayMode | Setting::Marker => {
+                let pane = &mut self.panes[self.focus];
+                if !pane.view.has_display_mode() {
+                    self.status = Some(format!(
+                        "the {} pane has no display mode or marker",
+                        pane.view.name()
+                    ));
+                    return;
+                }
+                if setting == Setting::DisplayMode {
+                    pane.display_mode =
+                        settings::step_display_mode(&pane.display_mode, direction).into();
+                } else {
+                    pane.marker = settings::step_marker(pane.marker, direction);
+                }
+                // what the spectrum panes show is also what gets saved and what the next
+                // layout starts with
+                if pane.view == View::Spectrum {
+                    self.args.display_mode = pane.display_mode.clone();
+                    self.args.marker = pane.marker;
+                }
+            }
+            Setting::MinFreq => {
+                self.args.min_freq =
+                    settings::step_freq(self.args.min_freq, direction).min(self.args.max_freq - 1.);
+                self.reset_view();
+            }
+            Setting::MaxFreq => {
+                self.args.max_freq =
+                    settings::step_freq(self.args.max_freq, direction).max(self.args.min_freq + 1.);
+                self.reset_view();
+            }
+            Setting::Fps => {
+                self.args.fps = settings::step_fps(self.args.fps, direction);
+                self.pretrigger.frame_interval = frame_interval(self.args.fps);
+            }
+        }
+    }
+
+    pub fn reset_view(&mut self) {
+        self.set_view(self.args.min_freq, self.args.max_freq);
+    }
+
+    // `factor` below 1 zooms in, the frequency at `center` stays where it is on screen
+    pub fn zoom(&mut self, factor: f64, center: Option<f64>) {
+        let (low, high) = self.freq_view;
+        let center = center
+            .filter(|freq| (low..=high).contains(freq))
+            .unwrap_or((low + high) / 2.);
+        self.set_view(
+            center - (center - low) * factor,
+            center + (high - center) * factor,
+        );
+    }
+
+    // moves the view by a fraction of its width, negative values pan towards lower frequencies
+    pub fn pan(&mut self, fraction: f64) {
+        let (low, high) = self.freq_view;
+        let shift = (high - low) * fraction;
+        let (min, max) = self.view_limits();
+        let shift = shift.clamp(min - low, max - high);
+        self.set_view(low + shift, high + shift);
+    }
+
+    // the view can go from 0 Hz up to Nyquist, or further if --max-freq asks for it
+    fn view_limits(&self) -> (f64, f64) {
+        let nyquist = f64::from(self.stream_info.sample_rate) / 2.;
+        (0., nyquist.max(self.args.max_freq))
+    }
+
+    fn set_view(&mut self, low: f64, high: f64) {
+        // a few bins at least, zooming further only magnifies a single line
+        let min_span = (self.freq_step * 4.).max(1.);
+        self.freq_view = clamp_view(low, high, self.view_limits(), min_span);
+    }
+
+    // rebuilds the stream with the current settings, a lost device picks them up once it
+    // reconnects
+    fn restart_stream(&mut self) {
+        if self.device_lost {
+            return;
+        }
+        if let Err(err) = self.update_input_device() {
+            self.schedule_retry(err.to_string());
+        }
+    }
+
+    pub fn toggle_recording(&mut self) -> Result<()> {
+        match self.recording {
+            Some(_) => self.stop_recording(),
+            None => self.start_recording(&recorder::timestamped_path("audiolyzer")),
+        }
+    }
+}
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+// enough for the waterfall to fill a tall terminal with two rows per cell
+const WATERFALL_ROWS: usize = 128;
+
+// the engine needs room for the whole spectrum of a block, which --bins may not cover
+pub fn spectrum_bins(args: &Args) -> usize {
+    args.bins.max(padded_len(args) / 2 + 1)
+}
+
+pub fn frame_interval(fps: u8) -> Duration {
+    Duration::from_secs(1) / u32::from(fps.max(1))
+}
+
+// the positions of the names containing `filter`, ignoring case
+fn matching_names(names: impl Iterator<Item = String>, filter: &str) -> Vec<usize> {
+    let filter = filter.to_lowercase();
+    names
+        .enumerate()
+        .filter(|(_, name)| name.to_lowercase().contains(&filter))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// the entry of `visible` `offset` places away from `current`, single steps wrap around and
+// longer ones stop at the ends
+fn step_within(visible: &[usize], current: usize, offset: i32) -> Option<usize> {
+    if visible.is_empty() {
+        return None;
+    }
+    let len = visible.len() as i32;
+    let pos = visible.iter().position(|i| *i == current).unwrap_or(0) as i32;
+    let pos = if offset.abs() == 1 {
+        (pos + offset).rem_euclid(len)
+    } else {
+        (pos + offset).clamp(0, len - 1)
+    };
+    Some(visible[pos as usize])
+}
+
+// keeps the view within `limits` and at least `min_span` wide, which with few large bins can
+// be all of it
+fn clamp_view(low: f64, high: f64, (min, max): (f64, f64), min_span: f64) -> (f64, f64) {
+    let min_span = min_span.min(max - min);
+    let low = low.clamp(min, max - min_span);
+    let high = high.clamp(low + min_span, max);
+    (low, high)
+}
+
+// how long a block is once --zero-pad padded it, which sets the bin spacing
+fn padded_len(args: &Args) -> usize {
+    args.fft_size as usize * args.zero_pad as usize
+}
+
+fn history_len(pretrigger: &PreTrigger, sample_rate: u32) -> usize {
+    if pretrigger.is_enabled() {
+        (pretrigger.history_secs() * f64::from(sample_rate)) as usize
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: (f64, f64) = (0., 22050.);
+
+    #[test]
+    fn views_within_the_limits_are_kept() {
+        assert_eq!(clamp_view(20., 20000., LIMITS, 172.), (20., 20000.));
+        assert_eq!(clamp_view(0., 22050., LIMITS, 172.), LIMITS);
+    }
+
+    #[test]
+    fn views_are_moved_back_inside_the_limits() {
+        assert_eq!(clamp_view(-100., 1000., LIMITS, 172.), (0., 1000.));
+        assert_eq!(clamp_view(1000., 30000., LIMITS, 172.), (1000., 22050.));
+        assert_eq!(clamp_view(22050., 30000., LIMITS, 172.), (21878., 22050.));
+    }
+
+    #[test]
+    fn zooming_stops_at_a_few_bins() {
+        assert_eq!(clamp_view(1000., 1010., LIMITS, 172.), (1000., 1172.));
+        assert_eq!(clamp_view(1000., 900., LIMITS, 172.), (1000., 1172.));
+    }
+
+    #[test]
+    fn a_tiny_fft_shows_the_whole_range() {
+        // 4 bins of a 4 sample block at 44.1 kHz are wider than everything up to Nyquist
+        let min_span = 44100. / 4. * 4.;
+        assert_eq!(clamp_view(20., 20000., LIMITS, min_span), LIMITS);
+        assert_eq!(clamp_view(5000., 6000., LIMITS, min_span), LIMITS);
+    }
+
+    fn names(names: &[&str]) -> impl Iterator<Item = String> {
+        names
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn devices_are_filtered_by_name_ignoring_case() {
+        let devices = ["USB Mic", "Built-in Input", "usb audio interface", ""];
+        assert_eq!(matching_names(names(&devices), "usb"), [0, 2]);
+        assert_eq!(matching_names(names(&devices), "IN"), [1, 2]);
+        assert_eq!(matching_names(names(&devices), ""), [0, 1, 2, 3]);
+        assert!(matching_names(names(&devices), "bluetooth").is_empty());
+    }
+
+    #[test]
+    fn single_steps_wrap_around() {
+        let visible = [1, 4, 6];
+        assert_eq!(step_within(&visible, 4, 1), Some(6));
+        assert_eq!(step_within(&visible, 6, 1), Some(1));
+        assert_eq!(step_within(&visible, 1, -1), Some(6));
+    }
+
+    #[test]
+    fn page_steps_stop_at_the_ends() {
+        let visible: Vec<usize> = (0..20).collect();
+        assert_eq!(step_within(&visible, 5, 10), Some(15));
+        assert_eq!(step_within(&visible, 15, 10), Some(19));
+        assert_eq!(step_within(&visible, 3, -10), Some(0));
+    }
+
+    #[test]
+    fn stepping_from_a_filtered_out_device_starts_at_the_top() {
+        assert_eq!(step_within(&[2, 3], 0, 1), Some(3));
+        assert_eq!(step_within(&[], 0, 1), None);
     }
 }
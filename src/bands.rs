@@ -0,0 +1,128 @@
+//! Aggregation of FFT bins into fractional-octave bands.
+
+/// A fractional-octave band and the level of the spectrum within it.
+#[derive(Clone, Debug)]
+pub struct Band {
+    /// Center frequency in Hz.
+    pub center: f64,
+    /// Lower edge in Hz.
+    pub low: f64,
+    /// Upper edge in Hz.
+    pub high: f64,
+    /// Summed power of the bins inside the band, in dB.
+    pub level_db: f64,
+}
+
+/// Groups a spectrum in dB, as returned by [`FFTEngine::get_bins`](crate::fft::FFTEngine::get_bins),
+/// into 1/`fraction` octave bands centered around 1 kHz, keeping the bands whose center lies
+/// between `min_freq` and `max_freq`.
+///
+/// Bands narrower than one bin take the level of the bin nearest to their center.
+pub fn octave_bands(
+    bins: &[f64],
+    freq_step: f64,
+    fraction: u32,
+    min_freq: f64,
+    max_freq: f64,
+) -> Vec<Band> {
+    let fraction = f64::from(fraction.max(1));
+    let first = (fraction * (min_freq / 1000.).log2()).ceil() as i32;
+    let last = (fraction * (max_freq / 1000.).log2()).floor() as i32;
+
+    (first..=last)
+        .filter_map(|k| {
+            let center = 1000. * 2f64.powf(f64::from(k) / fraction);
+            let half_width = 2f64.powf(1. / (2. * fraction));
+            let (low, high) = (center / half_width, center * half_width);
+
+            let lo_idx = (low / freq_step).ceil() as usize;
+            let hi_idx = ((high / freq_step).ceil() as usize).min(bins.len());
+            let level_db = if lo_idx < hi_idx {
+                let power: f64 = bins[lo_idx..hi_idx]
+                    .iter()
+                    .map(|db| 10f64.powf(db / 10.))
+                    .sum();
+                10. * power.log10()
+            } else {
+                *bins.get((center / freq_step).round() as usize)?
+            };
+
+            Some(Band {
+                center,
+                low,
+                high,
+                level_db,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centers_are_fractions_of_an_octave_around_1khz() {
+        let bins = vec![-60.; 22051];
+        let centers = |fraction| -> Vec<f64> {
+            octave_bands(&bins, 1., fraction, 20., 20000.)
+                .iter()
+                .map(|b| b.center)
+                .collect()
+        };
+        assert_eq!(
+            centers(1),
+            [31.25, 62.5, 125., 250., 500., 1000., 2000., 4000., 8000., 16000.]
+        );
+
+        let thirds = centers(3);
+        assert_eq!(thirds.len(), 29);
+        assert!((thirds[0] - 24.80).abs() < 0.01, "{}", thirds[0]);
+        assert_eq!(thirds[28], 16000.);
+        assert!(thirds.contains(&1000.));
+        for pair in thirds.windows(2) {
+            assert!((pair[1] / pair[0] - 2f64.powf(1. / 3.)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn edges_are_half_a_band_from_the_center() {
+        let bins = vec![-60.; 22051];
+        for band in octave_bands(&bins, 1., 3, 20., 20000.) {
+            assert!((band.high / band.low - 2f64.powf(1. / 3.)).abs() < 1e-12);
+            assert!((band.center * band.center - band.low * band.high).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn power_is_summed_over_the_bins_of_a_band() {
+        // 1 Hz bins, the 1 kHz octave runs from 707.1 to 1414.2 Hz
+        let bins = vec![-30.; 2000];
+        let bands = octave_bands(&bins, 1., 1, 1000., 1000.);
+        assert_eq!(bands.len(), 1);
+        let expected = -30. + 10. * 707f64.log10();
+        assert!(
+            (bands[0].level_db - expected).abs() < 1e-9,
+            "{}",
+            bands[0].level_db
+        );
+    }
+
+    #[test]
+    fn narrow_bands_take_the_nearest_bin() {
+        // 100 Hz bins are wider than the 1/24 octave bands around 100 Hz
+        let bins: Vec<f64> = (0..10).map(|i| -f64::from(i)).collect();
+        let bands = octave_bands(&bins, 100., 24, 95., 105.);
+        assert!(!bands.is_empty());
+        for band in bands {
+            assert_eq!(band.level_db, -1., "{} Hz", band.center);
+        }
+    }
+
+    #[test]
+    fn bands_past_the_spectrum_are_left_out() {
+        let bins = vec![-60.; 101];
+        let bands = octave_bands(&bins, 10., 1, 20., 20000.);
+        assert_eq!(bands.last().map(|b| b.center), Some(1000.));
+    }
+}
@@ -0,0 +1,686 @@
+use crate::{
+    error::{Error, Result},
+    inputs::InputEvent,
+};
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BufferSize, Device, FromSample, Host, HostId, Sample, SampleFormat, SampleRate, SizedSample,
+    Stream, StreamConfig, StreamInstant, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct StreamOutput {
+    // the latest `frame_len` samples, mixed down to mono
+    pub data: Vec<f32>,
+    pub frame_len: usize,
+    // set while recording, every buffer received from the device is also sent here
+    pub record_tx: Option<Sender<Vec<f32>>>,
+    // rolling buffer of the most recent `history_len` samples, used for pre-trigger dumps
+    pub history: VecDeque<f32>,
+    pub history_len: usize,
+    // the last error reported by the stream, picked up by the app to reconnect
+    pub error: Option<String>,
+    // the time between buffers from the device since the app last took them
+    pub callback_intervals: Vec<Duration>,
+    // counted since the stream started, outside of the lock
+    pub xruns: Arc<Xruns>,
+    // told about new samples, at most once until the app took them, so a busy app doesn't
+    // get a backlog of frames
+    pub frame_tx: Option<Sender<InputEvent>>,
+    pub frame_pending: bool,
+}
+
+impl StreamOutput {
+    fn push(&mut self, samples: &[f32]) {
+        self.data.extend_from_slice(samples);
+        let excess = self.data.len().saturating_sub(self.frame_len);
+        self.data.drain(..excess);
+
+        if let Some(record_tx) = &self.record_tx {
+            // the writer only goes away once recording stops, so a failed send can be ignored
+            let _ = record_tx.send(samples.to_vec());
+        }
+        if self.history_len > 0 {
+            self.history.extend(samples);
+            let excess = self.history.len().saturating_sub(self.history_len);
+            self.history.drain(..excess);
+        }
+        if !self.frame_pending {
+            if let Some(frame_tx) = &self.frame_tx {
+                // the app only goes away on shutdown
+                self.frame_pending = frame_tx.send(InputEvent::AudioFrame).is_ok();
+            }
+        }
+    }
+}
+
+// the audio callback must not log or wait, so it only counts xruns and the app thread logs them
+#[derive(Debug, Default)]
+pub struct Xruns {
+    count: AtomicU64,
+    dropped_samples: AtomicU64,
+}
+
+impl Xruns {
+    fn record(&self, dropped_samples: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.dropped_samples
+            .fetch_add(dropped_samples, Ordering::Relaxed);
+    }
+
+    pub fn load(&self) -> XrunCount {
+        XrunCount {
+            xruns: self.count.load(Ordering::Relaxed),
+            dropped_samples: self.dropped_samples.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XrunCount {
+    pub xruns: u64,
+    pub dropped_samples: u64,
+}
+
+impl XrunCount {
+    // what happened between `earlier` and this count, which starts over with a new stream
+    fn since(self, earlier: XrunCount) -> XrunCount {
+        if self.xruns < earlier.xruns {
+            return self;
+        }
+        XrunCount {
+            xruns: self.xruns - earlier.xruns,
+            dropped_samples: self.dropped_samples.saturating_sub(earlier.dropped_samples),
+        }
+    }
+
+    // takes over the latest count of `xruns` and logs the ones that are new
+    pub fn catch_up(&mut self, xruns: &Xruns) {
+        let current = xruns.load();
+        let new = current.since(*self);
+        if new.xruns > 0 {
+            log::warn!(
+                "{} xrun(s), {} samples of input lost",
+                new.xruns,
+                new.dropped_samples
+            );
+        }
+        *self = current;
+    }
+}
+
+// input that went missing between two callbacks shows up as a gap in their capture
+// timestamps, anything up to half a block late is jitter
+fn lost_input(elapsed: Duration, previous_block: Duration) -> Option<Duration> {
+    (elapsed > previous_block * 3 / 2).then(|| elapsed - previous_block)
+}
+
+// plenty for a frame even with tiny device buffers
+const MAX_CALLBACK_INTERVALS: usize = 1000;
+
+// looks up a host such as alsa or jack by name, without a name the platform default is used
+pub fn find_host(name: Option<&str>) -> Result<Host> {
+    let Some(name) = name else {
+        return Ok(cpal::default_host());
+    };
+
+    let hosts = cpal::available_hosts();
+    let id = hosts
+        .iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let available: Vec<_> = hosts.iter().map(|id| id.name()).collect();
+            Error::HostUnavailable(format!("{name} (available: {})", available.join(", ")))
+        })?;
+    Ok(cpal::host_from_id(*id)?)
+}
+
+// prefers the device requested by name, then BlackHole (the loopback device this was
+// originally written against), then whatever the host reports as its default input
+pub fn find_device_idx(host: &Host, in_devices: &[Device], name: Option<&str>) -> Option<usize> {
+    let position = |wanted: &str| {
+        in_devices
+            .iter()
+            .position(|d| d.name().map(|n| n == wanted).unwrap_or(false))
+    };
+
+    if let Some(name) = name {
+        return position(name);
+    }
+
+    position("BlackHole 2ch").or_else(|| {
+        let default_name = host.default_input_device()?.name().ok()?;
+        position(&default_name)
+    })
+}
+
+// what the device picker shows about a device, queried only when it gets selected since
+// some hosts have to open the device to answer
+pub struct DeviceDetails {
+    pub default_config: Option<SupportedStreamConfig>,
+    pub configs: std::result::Result<Vec<SupportedStreamConfigRange>, String>,
+}
+
+impl DeviceDetails {
+    pub fn query(device: &Device) -> Self {
+        DeviceDetails {
+            default_config: device.default_input_config().ok(),
+            configs: device
+                .supported_input_configs()
+                .map(Iterator::collect)
+                .map_err(|err| err.to_string()),
+        }
+    }
+}
+
+// periodically lists the names of the input devices on a background thread, since
+// enumerating can take long enough on some hosts to stall a frame
+pub struct DeviceWatcher {
+    pub host_id: HostId,
+    rx: Receiver<Vec<String>>,
+}
+
+impl DeviceWatcher {
+    pub fn spawn(host_id: HostId, interval: Duration) -> Self {
+        let (tx, rx) = channel();
+        thread::spawn(move || loop {
+            let names = cpal::host_from_id(host_id)
+                .ok()
+                .and_then(|host| host.input_devices().ok())
+                .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+                .unwrap_or_default();
+            if tx.send(names).is_err() {
+                break;
+            }
+            thread::sleep(interval);
+        });
+        DeviceWatcher { host_id, rx }
+    }
+
+    // the most recent device list, if a new one arrived since the last call
+    pub fn latest(&self) -> Option<Vec<String>> {
+        self.rx.try_iter().last()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_format: SampleFormat,
+    pub buffer_size: BufferSize,
+}
+
+impl Display for StreamInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} Hz, {} ch, {}",
+            self.sample_rate, self.channels, self.sample_format
+        )?;
+        if let BufferSize::Fixed(frames) = self.buffer_size {
+            write!(f, ", {frames} frames")?;
+        }
+        Ok(())
+    }
+}
+
+// lower is better, formats that cannot be converted to f32 are not used at all
+fn format_rank(format: SampleFormat) -> Option<u8> {
+    match format {
+        SampleFormat::F32 => Some(0),
+        SampleFormat::I32 => Some(1),
+        SampleFormat::I16 => Some(2),
+        SampleFormat::U16 => Some(3),
+        SampleFormat::F64 => Some(4),
+        SampleFormat::I64 | SampleFormat::U32 | SampleFormat::U64 => Some(5),
+        SampleFormat::I8 | SampleFormat::U8 => Some(6),
+        _ => None,
+    }
+}
+
+// the config `build_stream` opens the device with
+pub fn negotiate_config(
+    device: &Device,
+    sample_rate: u32,
+    channels: u16,
+    fft_size: u32,
+) -> Result<StreamInfo> {
+    choose_config(
+        device.supported_input_configs()?,
+        sample_rate,
+        channels,
+        fft_size,
+    )
+}
+
+// picks the supported config closest to the requested one, in order of sample rate, channel
+// count and sample format
+fn choose_config(
+    ranges: impl IntoIterator<Item = SupportedStreamConfigRange>,
+    sample_rate: u32,
+    channels: u16,
+    fft_size: u32,
+) -> Result<StreamInfo> {
+    let range = ranges
+        .into_iter()
+        .filter(|range| format_rank(range.sample_format()).is_some())
+        .min_by_key(|range| {
+            let rate = sample_rate.clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+            (
+                rate.abs_diff(sample_rate),
+                range.channels().abs_diff(channels),
+                format_rank(range.sample_format()),
+            )
+        })
+        .ok_or_else(|| Error::UnsupportedConfig("device offers no usable input config".into()))?;
+
+    let buffer_size = match range.buffer_size() {
+        SupportedBufferSize::Range { min, max } if (*min..=*max).contains(&fft_size) => {
+            BufferSize::Fixed(fft_size)
+        }
+        _ => BufferSize::Default,
+    };
+
+    Ok(StreamInfo {
+        sample_rate: sample_rate.clamp(range.min_sample_rate().0, range.max_sample_rate().0),
+        channels: range.channels(),
+        sample_format: range.sample_format(),
+        buffer_size,
+    })
+}
+
+// negotiates a config close to the requested one and starts capturing, every callback is
+// mixed down to mono f32 and the latest `fft_size` samples are kept in `data`
+pub fn build_stream(
+    device: &Device,
+    sample_rate: u32,
+    channels: u16,
+    fft_size: u32,
+    data_lock: Arc<Mutex<StreamOutput>>,
+) -> Result<(Stream, StreamInfo)> {
+    let info = negotiate_config(device, sample_rate, channels, fft_size)?;
+    if let Ok(mut streamoutput) = data_lock.lock() {
+        streamoutput.frame_len = fft_size as usize;
+    }
+
+    let stream = match info.sample_format {
+        SampleFormat::I8 => build_typed::<i8>(device, &info, data_lock),
+        SampleFormat::I16 => build_typed::<i16>(device, &info, data_lock),
+        SampleFormat::I32 => build_typed::<i32>(device, &info, data_lock),
+        SampleFormat::I64 => build_typed::<i64>(device, &info, data_lock),
+        SampleFormat::U8 => build_typed::<u8>(device, &info, data_lock),
+        SampleFormat::U16 => build_typed::<u16>(device, &info, data_lock),
+        SampleFormat::U32 => build_typed::<u32>(device, &info, data_lock),
+        SampleFormat::U64 => build_typed::<u64>(device, &info, data_lock),
+        SampleFormat::F64 => build_typed::<f64>(device, &info, data_lock),
+        _ => build_typed::<f32>(device, &info, data_lock),
+    }?;
+    stream.play()?;
+    log::info!(
+        "capturing from {} at {info}",
+        device.name().unwrap_or_else(|_| "unknown".into())
+    );
+    Ok((stream, info))
+}
+
+// averages the channels of every interleaved frame
+fn mix_to_mono<T>(data: &[T], channels: usize) -> Vec<f32>
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    data.chunks(channels)
+        .map(|frame| frame.iter().map(|s| f32::from_sample(*s)).sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+fn build_typed<T>(
+    device: &Device,
+    info: &StreamInfo,
+    data_lock: Arc<Mutex<StreamOutput>>,
+) -> Result<Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let config = StreamConfig {
+        channels: info.channels,
+        sample_rate: SampleRate(info.sample_rate),
+        buffer_size: info.buffer_size,
+    };
+    let channels = usize::from(info.channels);
+
+    let err_lock = data_lock.clone();
+    let xruns = data_lock
+        .lock()
+        .map(|res| res.xruns.clone())
+        .unwrap_or_default();
+    let sample_rate = f64::from(info.sample_rate);
+    // when the previous callback was captured and how long its block was
+    let mut previous: Option<(StreamInstant, Duration)> = None;
+    let mut last_callback: Option<Instant> = None;
+    let stream = device.build_input_stream(
+        &config,
+        move |data: &[T], callback: &cpal::InputCallbackInfo| {
+            let captured = callback.timestamp().capture;
+            let block = Duration::from_secs_f64((data.len() / channels) as f64 / sample_rate);
+            let lost = previous.and_then(|(at, len)| {
+                captured
+                    .duration_since(&at)
+                    .and_then(|elapsed| lost_input(elapsed, len))
+            });
+            if let Some(lost) = lost {
+                xruns.record((lost.as_secs_f64() * sample_rate) as u64);
+            }
+            previous = Some((captured, block));
+            let now = Instant::now();
+            let interval = last_callback.map(|last| now - last);
+            last_callback = Some(now);
+
+            let mono = mix_to_mono(data, channels);
+            if let Ok(mut streamoutput) = data_lock.lock() {
+                streamoutput.push(&mono);
+                // nobody takes them while the app is stalled, so the list stops growing there
+                if let Some(interval) = interval {
+                    if streamoutput.callback_intervals.len() < MAX_CALLBACK_INTERVALS {
+                        streamoutput.callback_intervals.push(interval);
+                    }
+                }
+            }
+        },
+        move |err| {
+            log::warn!("stream error: {err}");
+            if let Ok(mut streamoutput) = err_lock.lock() {
+                streamoutput.error = Some(err.to_string());
+            }
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(frame_len: usize, history_len: usize) -> StreamOutput {
+        StreamOutput {
+            frame_len,
+            history_len,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_the_latest_frame_is_kept() {
+        let mut out = output(4, 0);
+        out.push(&[1., 2., 3.]);
+        assert_eq!(out.data, [1., 2., 3.]);
+        out.push(&[4., 5., 6.]);
+        assert_eq!(out.data, [3., 4., 5., 6.]);
+        assert!(out.history.is_empty());
+    }
+
+    #[test]
+    fn history_rolls_over_separately() {
+        let mut out = output(2, 5);
+        for block in [[1., 2., 3.], [4., 5., 6.], [7., 8., 9.]] {
+            out.push(&block);
+        }
+        assert_eq!(out.data, [8., 9.]);
+        assert_eq!(out.history, [5., 6., 7., 8., 9.]);
+    }
+
+    #[test]
+    fn recordings_get_every_buffer() {
+        let (record_tx, record_rx) = channel();
+        let mut out = output(2, 0);
+        out.record_tx = Some(record_tx);
+        out.push(&[1., 2., 3.]);
+        out.push(&[4.]);
+        assert_eq!(
+            record_rx.try_iter().collect::<Vec<_>>(),
+            [vec![1., 2., 3.], vec![4.]]
+        );
+    }
+
+    #[test]
+    fn the_app_is_told_once_until_it_took_the_samples() {
+        let (frame_tx, frame_rx) = channel();
+        let mut out = output(2, 0);
+        out.frame_tx = Some(frame_tx);
+        out.push(&[1.]);
+        out.push(&[2.]);
+        assert_eq!(frame_rx.try_iter().count(), 1);
+        assert!(out.frame_pending);
+
+        // as done by the app when it takes the data
+        out.frame_pending = false;
+        out.push(&[3.]);
+        assert!(matches!(frame_rx.try_recv(), Ok(InputEvent::AudioFrame)));
+    }
+
+    #[test]
+    fn hosts_are_found_by_name_ignoring_case() {
+        let default = cpal::default_host().id();
+        assert_eq!(find_host(None).unwrap().id(), default);
+        let name = default.name().to_uppercase();
+        assert_eq!(find_host(Some(&name)).unwrap().id(), default);
+    }
+
+    #[test]
+    fn unknown_hosts_list_the_available_ones() {
+        let Err(err) = find_host(Some("no-such-host")) else {
+            panic!("found a host that doesn't exist");
+        };
+        let available: Vec<_> = cpal::available_hosts().iter().map(|id| id.name()).collect();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "audio host unavailable: no-such-host (available: {})",
+                available.join(", ")
+            )
+        );
+    }
+
+    fn range(channels: u16, rates: (u32, u32), format: SampleFormat) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(rates.0),
+            SampleRate(rates.1),
+            SupportedBufferSize::Range { min: 64, max: 4096 },
+            format,
+        )
+    }
+
+    #[test]
+    fn the_requested_config_is_used_when_supported() {
+        let ranges = [
+            range(2, (8000, 192000), SampleFormat::I16),
+            range(1, (8000, 192000), SampleFormat::F32),
+        ];
+        let info = choose_config(ranges, 48000, 1, 1024).unwrap();
+        assert_eq!(info.sample_rate, 48000);
+        assert_eq!(info.channels, 1);
+        assert_eq!(info.sample_format, SampleFormat::F32);
+        assert_eq!(info.buffer_size, BufferSize::Fixed(1024));
+        assert_eq!(info.to_string(), "48000 Hz, 1 ch, f32, 1024 frames");
+    }
+
+    #[test]
+    fn the_sample_rate_comes_first_then_channels_then_format() {
+        // a stereo-only device that supports the rate wins over a mono one that doesn't
+        let ranges = [
+            range(1, (8000, 16000), SampleFormat::F32),
+            range(2, (44100, 48000), SampleFormat::I16),
+            range(2, (44100, 48000), SampleFormat::I32),
+        ];
+        let info = choose_config(ranges, 44100, 1, 1024).unwrap();
+        assert_eq!((info.sample_rate, info.channels), (44100, 2));
+        assert_eq!(info.sample_format, SampleFormat::I32);
+
+        // outside of every range the closest supported rate is used
+        let ranges = [
+            range(1, (8000, 16000), SampleFormat::F32),
+            range(1, (32000, 48000), SampleFormat::F32),
+        ];
+        assert_eq!(
+            choose_config(ranges, 96000, 1, 1024).unwrap().sample_rate,
+            48000
+        );
+        assert_eq!(
+            choose_config(ranges, 20000, 1, 1024).unwrap().sample_rate,
+            16000
+        );
+    }
+
+    #[test]
+    fn unsupported_buffer_sizes_fall_back_to_the_default() {
+        let info = choose_config(
+            [range(1, (44100, 44100), SampleFormat::F32)],
+            44100,
+            1,
+            8192,
+        )
+        .unwrap();
+        assert_eq!(info.buffer_size, BufferSize::Default);
+        assert_eq!(info.to_string(), "44100 Hz, 1 ch, f32");
+
+        let unknown = SupportedStreamConfigRange::new(
+            1,
+            SampleRate(44100),
+            SampleRate(44100),
+            SupportedBufferSize::Unknown,
+            SampleFormat::F32,
+        );
+        let info = choose_config([unknown], 44100, 1, 1024).unwrap();
+        assert_eq!(info.buffer_size, BufferSize::Default);
+    }
+
+    #[test]
+    fn devices_without_usable_configs_are_rejected() {
+        assert!(matches!(
+            choose_config([], 44100, 1, 1024),
+            Err(Error::UnsupportedConfig(_))
+        ));
+    }
+
+    #[test]
+    fn frames_are_mixed_down_to_mono() {
+        assert_eq!(mix_to_mono(&[0.5f32, -0.5, 1., 0.], 2), [0., 0.5]);
+        assert_eq!(mix_to_mono(&[0.25f32, 0.5, 0.75], 1), [0.25, 0.5, 0.75]);
+        assert_eq!(
+            mix_to_mono(&[0.3f32, 0.3, 0.3, 0.1, 0.1, 0.1], 3),
+            [0.3, 0.1]
+        );
+        // a partial frame at the end is averaged over the channels it has
+        assert_eq!(mix_to_mono(&[1f32, 0., 0.5], 2), [0.5, 0.5]);
+    }
+
+    #[test]
+    fn integer_samples_are_converted_to_float() {
+        assert_eq!(mix_to_mono(&[i16::MIN, 0, 16384], 1), [-1., 0., 0.5]);
+        assert_eq!(mix_to_mono(&[i16::MIN, 16384], 2), [-0.25]);
+        // unsigned samples are centered around the middle of their range
+        assert_eq!(mix_to_mono(&[0u8, 128, 192], 1), [-1., 0., 0.5]);
+        assert_eq!(mix_to_mono(&[i32::MIN / 2], 1), [-0.5]);
+    }
+
+    #[test]
+    fn the_watcher_reports_the_newest_device_list() {
+        let (tx, rx) = channel();
+        let watcher = DeviceWatcher {
+            host_id: cpal::default_host().id(),
+            rx,
+        };
+        assert_eq!(watcher.latest(), None);
+
+        tx.send(vec!["mic".to_string()]).unwrap();
+        tx.send(vec![]).unwrap();
+        tx.send(vec!["mic".to_string(), "usb".to_string()]).unwrap();
+        assert_eq!(
+            watcher.latest(),
+            Some(vec!["mic".to_string(), "usb".to_string()])
+        );
+        // each list is only reported once
+        assert_eq!(watcher.latest(), None);
+    }
+
+    #[test]
+    fn the_watcher_lists_devices_in_the_background() {
+        let host_id = cpal::default_host().id();
+        let watcher = DeviceWatcher::spawn(host_id, Duration::from_millis(10));
+        assert_eq!(watcher.host_id, host_id);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while watcher.latest().is_none() {
+            assert!(Instant::now() < deadline, "no device list arrived");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn late_callbacks_lost_input() {
+        let block = Duration::from_millis(10);
+        assert_eq!(lost_input(Duration::from_millis(10), block), None);
+        // jitter of up to half a block
+        assert_eq!(lost_input(Duration::from_millis(15), block), None);
+        assert_eq!(
+            lost_input(Duration::from_millis(40), block),
+            Some(Duration::from_millis(30))
+        );
+    }
+
+    #[test]
+    fn xruns_are_counted_across_threads() {
+        let xruns = Arc::new(Xruns::default());
+        let callback = xruns.clone();
+        thread::spawn(move || {
+            callback.record(480);
+            callback.record(20);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(
+            xruns.load(),
+            XrunCount {
+                xruns: 2,
+                dropped_samples: 500,
+            }
+        );
+    }
+
+    #[test]
+    fn catching_up_with_xruns() {
+        let xruns = Xruns::default();
+        let mut seen = XrunCount::default();
+        seen.catch_up(&xruns);
+        assert_eq!(seen, XrunCount::default());
+
+        xruns.record(100);
+        seen.catch_up(&xruns);
+        assert_eq!(seen, xruns.load());
+
+        let count = |xruns, dropped_samples| XrunCount {
+            xruns,
+            dropped_samples,
+        };
+        assert_eq!(count(5, 900).since(count(3, 400)), count(2, 500));
+        // a new stream counts from zero again
+        assert_eq!(count(1, 50).since(count(3, 400)), count(1, 50));
+    }
+}
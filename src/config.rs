@@ -0,0 +1,445 @@
+use crate::{
+    app::Args,
+    error::{Error, Result},
+};
+
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::{CommandFactory, Parser};
+use toml_edit::{table, value, DocumentMut, Item, Table, Value};
+
+// flags that pick the config itself and make no sense inside of it
+const CLI_ONLY_KEYS: &[&str] = &["config", "profile"];
+
+// tables holding something other than flags, e.g. [keys] for the key bindings
+const SECTIONS: &[&str] = &["profiles", "keys", "themes", "layouts"];
+
+// which names a file's settings were written with, files without the key predate it and are
+// migrated when loaded. Version 2 renamed the 4-term window `blackman` to `blackman-harris`.
+const VERSION_KEY: &str = "config_version";
+const VERSION: i64 = 2;
+
+// a TOML file whose top-level keys are defaults for the command line flags of the same name
+// (with underscores), and whose [profiles.<name>] tables override them when picked with
+// --profile
+pub struct Config {
+    pub path: PathBuf,
+    pub profile: Option<String>,
+    // what was changed while migrating the file, for the user to be told about
+    pub notices: Vec<String>,
+    doc: DocumentMut,
+}
+
+pub fn default_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_default()
+        .join("audiolyzer")
+        .join("config.toml")
+}
+
+impl Config {
+    // a missing file is only an error when it was asked for explicitly
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Config> {
+        let explicit = path.is_some();
+        let path = path.map(Path::to_path_buf).unwrap_or_else(default_path);
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if !explicit && err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(Error::Config(format!("{}: {err}", path.display())));
+            }
+        };
+        Config::parse(path, profile, &contents)
+    }
+
+    // `path` is only where the config gets saved to and what errors refer to
+    pub fn parse(path: PathBuf, profile: Option<&str>, contents: &str) -> Result<Config> {
+        let doc = contents
+            .parse::<DocumentMut>()
+            .map_err(|err| Error::Config(format!("{}: {err}", path.display())))?;
+        let mut config = Config {
+            path,
+            profile: profile.map(String::from),
+            notices: vec![],
+            doc,
+        };
+        config.migrate();
+        if let Some(profile) = profile {
+            if config.profile_table(profile).is_none() {
+                return Err(Error::Config(format!(
+                    "profile {profile} not found in {}",
+                    config.path.display()
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    // rewrites settings whose meaning changed since the file's version in memory, they only
+    // end up in the file the next time settings are saved
+    fn migrate(&mut self) {
+        let version = self
+            .doc
+            .get(VERSION_KEY)
+            .and_then(Item::as_integer)
+            .unwrap_or(1);
+        if version < 2 {
+            let mut tables = vec![String::new()];
+            if let Some(profiles) = self.doc.get("profiles").and_then(Item::as_table) {
+                tables.extend(profiles.iter().map(|(name, _)| name.to_string()));
+            }
+            for name in tables {
+                let table = match name.as_str() {
+                    "" => Some(self.doc.as_table_mut()),
+                    profile => self.doc["profiles"][profile].as_table_mut(),
+                };
+                let Some(window) = table.and_then(|t| t.get_mut("window")) else {
+                    continue;
+                };
+                if window
+                    .as_str()
+                    .is_some_and(|w| w.trim().eq_ignore_ascii_case("blackman"))
+                {
+                    *window = value("blackman-harris");
+                    let place = match name.as_str() {
+                        "" => String::new(),
+                        profile => format!(" of profile {profile}"),
+                    };
+                    self.notices.push(format!(
+                        "window \"blackman\"{place} in {} is read as \"blackman-harris\", which \
+                         it meant before {VERSION_KEY} {VERSION}",
+                        self.path.display()
+                    ));
+                }
+            }
+        }
+        if version < VERSION {
+            self.doc.insert(VERSION_KEY, value(VERSION));
+        }
+    }
+
+    fn profile_table(&self, profile: &str) -> Option<&Table> {
+        self.doc.get("profiles")?.get(profile)?.as_table()
+    }
+
+    // the top-level settings followed by those of the active profile, so later ones win
+    fn settings(&self) -> Vec<(&str, &Item)> {
+        let mut settings: Vec<_> = self.doc.iter().collect();
+        if let Some(profile) = self.profile.as_deref().and_then(|p| self.profile_table(p)) {
+            settings.extend(profile.iter());
+        }
+        settings.retain(|(key, _)| !SECTIONS.contains(key) && *key != VERSION_KEY);
+        settings
+    }
+
+    // the entries of a section such as [keys], followed by those of the same table in the
+    // active profile
+    pub fn section(&self, name: &str) -> Vec<(&str, &Item)> {
+        let mut entries: Vec<_> = self
+            .doc
+            .get(name)
+            .and_then(Item::as_table)
+            .map(|table| table.iter().collect())
+            .unwrap_or_default();
+        if let Some(table) = self
+            .profile
+            .as_deref()
+            .and_then(|p| self.profile_table(p))
+            .and_then(|profile| profile.get(name))
+            .and_then(Item::as_table)
+        {
+            entries.extend(table.iter());
+        }
+        entries
+    }
+
+    // turns the settings into command line arguments, so clap validates them just like flags
+    pub fn cli_args(&self) -> Result<Vec<OsString>> {
+        let command = Args::command();
+        let mut cli_args = vec![];
+
+        for (key, item) in self.settings() {
+            let flag = key.replace('_', "-");
+            let known = command
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(flag.as_str()));
+            if !known || CLI_ONLY_KEYS.contains(&key) {
+                return Err(Error::Config(format!(
+                    "unknown setting {key} in {}",
+                    self.path.display()
+                )));
+            }
+
+            let unsupported = || {
+                Error::Config(format!(
+                    "unsupported value for {key} in {}",
+                    self.path.display()
+                ))
+            };
+            // an array repeats the flag once per element, e.g. for several --curve files
+            let values: Vec<&Value> = match item.as_value() {
+                Some(Value::Boolean(b)) => {
+                    if *b.value() {
+                        cli_args.push(format!("--{flag}").into());
+                    }
+                    continue;
+                }
+                Some(Value::Array(array)) => array.iter().collect(),
+                Some(value) => vec![value],
+                None => return Err(unsupported()),
+            };
+            for value in values {
+                let value = match value {
+                    Value::String(s) => s.value().clone(),
+                    Value::Integer(i) => i.value().to_string(),
+                    Value::Float(f) => f.value().to_string(),
+                    _ => return Err(unsupported()),
+                };
+                cli_args.push(format!("--{flag}").into());
+                cli_args.push(value.into());
+            }
+        }
+
+        Ok(cli_args)
+    }
+
+    // stores the settings in the active profile, or at the top level without one, keeping the
+    // rest of the file as it is
+    pub fn save(&mut self, settings: &[(&str, Value)]) -> Result<()> {
+        let target = match &self.profile {
+            Some(profile) => self
+                .doc
+                .entry("profiles")
+                .or_insert(table())
+                .as_table_mut()
+                .and_then(|profiles| profiles.entry(profile).or_insert(table()).as_table_mut()),
+            None => Some(self.doc.as_table_mut()),
+        }
+        .ok_or_else(|| Error::Config(format!("malformed profiles in {}", self.path.display())))?;
+
+        for (key, value) in settings {
+            target.insert(key, Item::Value(value.clone()));
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, self.doc.to_string())?;
+        Ok(())
+    }
+}
+
+// parses the command line once to find the config, then again with the config's settings
+// placed in front of the real arguments so that flags given on the command line win
+pub fn parse_args() -> Result<(Args, Config)> {
+    // the first pass only looks for --config and --profile, settings such as `headless` that
+    // other flags depend on may still be missing from it
+    let matches = Args::command().ignore_errors(true).get_matches();
+    let config = Config::load(
+        matches.get_one::<PathBuf>("config").map(PathBuf::as_path),
+        matches.get_one::<String>("profile").map(String::as_str),
+    )?;
+
+    let mut argv: Vec<OsString> = env::args_os().take(1).collect();
+    argv.extend(config.cli_args()?);
+    argv.extend(env::args_os().skip(1));
+
+    Ok((Args::parse_from(argv), config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use audiolyzer::fft::WindowType;
+
+    fn cli_args(toml: &str, profile: Option<&str>) -> Result<Vec<String>> {
+        let config = Config::parse(PathBuf::from("config.toml"), profile, toml)?;
+        Ok(config
+            .cli_args()?
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn settings_become_flags() {
+        let cases: [(&str, &[&str]); 8] = [
+            ("", &[]),
+            ("fft_size = 2048", &["--fft-size", "2048"]),
+            ("smoothing_constant = 0.5", &["--smoothing-constant", "0.5"]),
+            ("window = \"kaiser:6\"", &["--window", "kaiser:6"]),
+            ("headless = true", &["--headless"]),
+            ("headless = false", &[]),
+            (
+                "curve = [\"a.csv\", \"b.csv\"]",
+                &["--curve", "a.csv", "--curve", "b.csv"],
+            ),
+            // sections are not flags
+            ("fps = 30\n[keys]\nquit = \"x\"", &["--fps", "30"]),
+        ];
+        for (toml, args) in cases {
+            let got = cli_args(toml, None).unwrap_or_else(|err| panic!("{toml:?}: {err}"));
+            assert_eq!(got, args, "{toml:?}");
+        }
+    }
+
+    #[test]
+    fn profile_settings_come_last() {
+        let toml = "fft_size = 2048\nfps = 30\n[profiles.p]\nfft_size = 8192";
+        assert_eq!(
+            cli_args(toml, Some("p")).unwrap(),
+            ["--fft-size", "2048", "--fps", "30", "--fft-size", "8192"]
+        );
+        assert_eq!(
+            cli_args(toml, None).unwrap(),
+            ["--fft-size", "2048", "--fps", "30"]
+        );
+    }
+
+    #[test]
+    fn malformed_settings() {
+        let cases = [
+            ("volume = 11", "unknown setting volume in config.toml"),
+            (
+                "config = \"other.toml\"",
+                "unknown setting config in config.toml",
+            ),
+            ("profile = \"p\"", "unknown setting profile in config.toml"),
+            (
+                "device = { name = \"x\" }",
+                "unsupported value for device in config.toml",
+            ),
+            (
+                "curve = [[\"a.csv\"]]",
+                "unsupported value for curve in config.toml",
+            ),
+            (
+                "[device]\nname = \"x\"",
+                "unsupported value for device in config.toml",
+            ),
+        ];
+        for (toml, message) in cases {
+            let Err(err) = cli_args(toml, None) else {
+                panic!("{toml:?} was accepted");
+            };
+            assert_eq!(err.to_string(), format!("invalid configuration: {message}"));
+        }
+    }
+
+    #[test]
+    fn malformed_files() {
+        assert!(Config::parse(PathBuf::from("config.toml"), None, "fft_size = ").is_err());
+        let Err(err) = Config::parse(PathBuf::from("config.toml"), Some("p"), "") else {
+            panic!("found a missing profile");
+        };
+        assert_eq!(
+            err.to_string(),
+            "invalid configuration: profile p not found in config.toml"
+        );
+    }
+
+    fn parse(toml: &str) -> std::result::Result<Args, clap::Error> {
+        let mut argv = vec!["audiolyzer".to_string()];
+        argv.extend(cli_args(toml, None).unwrap());
+        // given again on the command line, which wins
+        argv.extend(["--fps".into(), "30".into()]);
+        Args::try_parse_from(argv)
+    }
+
+    #[test]
+    fn flags_are_parsed_like_the_command_line() {
+        let args =
+            parse("fft_size = 2048\nwindow = \"tukey:0.25\"\nheadless = true\nfps = 50").unwrap();
+        assert_eq!(args.fft_size, 2048);
+        assert_eq!(args.window, WindowType::Tukey(0.25));
+        assert!(args.headless);
+        assert_eq!(args.fps, 30);
+
+        // invalid values are rejected by clap, just like on the command line
+        for toml in [
+            "fps = 0",
+            "window = \"welch\"",
+            "fft_size = -1",
+            "zero_pad = 32",
+        ] {
+            assert!(parse(toml).is_err(), "{toml}");
+        }
+    }
+
+    #[test]
+    fn saved_settings_round_trip() {
+        let path = env::temp_dir().join(format!("audiolyzer-test-{}.toml", std::process::id()));
+        let toml = "# kept\nfft_size = 2048\n[profiles.p]\nfps = 30\n";
+        let mut config = Config::parse(path.clone(), Some("p"), toml).unwrap();
+        config
+            .save(&[("window", "flat-top".into()), ("fps", 50.into())])
+            .unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        let loaded = Config::load(Some(&path), Some("p")).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(saved.starts_with("# kept\n"), "{saved}");
+        assert_eq!(
+            loaded
+                .cli_args()
+                .unwrap()
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect::<Vec<_>>(),
+            ["--fft-size", "2048", "--fps", "50", "--window", "flat-top"]
+        );
+    }
+
+    #[test]
+    fn old_files_keep_the_window_they_meant() {
+        let toml = "window = \"blackman\"\n[profiles.p]\nwindow = \"Blackman\"\n";
+        let config = Config::parse(PathBuf::from("config.toml"), Some("p"), toml).unwrap();
+        assert_eq!(config.notices.len(), 2);
+        assert_eq!(
+            config.notices[1],
+            "window \"blackman\" of profile p in config.toml is read as \"blackman-harris\", \
+             which it meant before config_version 2"
+        );
+        assert_eq!(
+            config
+                .cli_args()
+                .unwrap()
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect::<Vec<_>>(),
+            ["--window", "blackman-harris", "--window", "blackman-harris"]
+        );
+    }
+
+    #[test]
+    fn current_files_are_left_alone() {
+        let toml = "config_version = 2\nwindow = \"blackman\"\n";
+        let config = Config::parse(PathBuf::from("config.toml"), None, toml).unwrap();
+        assert!(config.notices.is_empty());
+        assert_eq!(config.cli_args().unwrap(), ["--window", "blackman"]);
+    }
+
+    #[test]
+    fn migrations_are_saved_with_the_version() {
+        let path = env::temp_dir().join(format!("audiolyzer-migrate-{}.toml", std::process::id()));
+        let mut config = Config::parse(path.clone(), None, "window = \"blackman\"\n").unwrap();
+        config.save(&[("fps", 50.into())]).unwrap();
+
+        let loaded = Config::load(Some(&path), None).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(saved.contains("config_version = 2"), "{saved}");
+        assert!(loaded.notices.is_empty());
+        assert_eq!(
+            loaded.cli_args().unwrap(),
+            ["--window", "blackman-harris", "--fps", "50"]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! A constant-Q transform, whose bins are spaced evenly on a logarithmic frequency axis and
+//! all span the same fraction of an octave.
+//!
+//! Every bin correlates the block with its own windowed complex exponential, which is as long
+//! as the bin's bandwidth requires: long for low frequencies and short for high ones. The
+//! lowest bin is the lowest one whose kernel still fits in the block, so every bin has the
+//! same Q and the block length sets how low the transform reaches. The correlation is computed in the frequency domain from one FFT of
+//! the whole block against sparse spectral kernels, which are computed once per configuration
+//! (Brown and Puckette, "An efficient algorithm for the calculation of a constant Q
+//! transform", 1992).
+
+use crate::fft::WindowType;
+
+use realfft::{num_complex::Complex, RealFftPlanner};
+
+/// Frequency every bin is tuned relative to, so with a multiple of 12 bins per octave bins
+/// land on the notes of equal temperament.
+pub const TUNING_HZ: f64 = 440.;
+
+// spectral kernel values below this fraction of the kernel's largest one are dropped, which
+// keeps the kernels sparse without a visible difference in the result
+const SPARSITY: f64 = 0.0054;
+
+/// The spectral kernels of one block length, sample rate, resolution and window.
+pub struct ConstantQ {
+    block_len: usize,
+    sample_rate: u32,
+    bins_per_octave: u32,
+    window: WindowType,
+    freqs: Vec<f64>,
+    // per bin, the conjugated kernel values at the FFT bins they are not negligible at
+    kernels: Vec<Vec<(usize, Complex<f64>)>>,
+}
+
+impl ConstantQ {
+    /// Computes the kernels for blocks of `block_len` samples with `bins_per_octave` bins
+    /// between the lowest frequency the block can resolve at constant Q and half the sample
+    /// rate, each windowed with `window`.
+    ///
+    /// A bin at `f` Hz needs `Q * sample_rate / f` samples, with `Q = 1 / (2^(1 /
+    /// bins_per_octave) - 1)`, so the lowest bin is at about `Q * sample_rate / block_len` Hz.
+    pub fn new(
+        block_len: usize,
+        sample_rate: u32,
+        bins_per_octave: u32,
+        window: WindowType,
+        planner: &mut RealFftPlanner<f64>,
+    ) -> Self {
+        let fs = f64::from(sample_rate);
+        let bpo = f64::from(bins_per_octave.max(1));
+        let q = 1. / (2f64.powf(1. / bpo) - 1.);
+
+        // from the first bin whose kernel fits in the block up to the last bin whose band
+        // still fits below half the rate
+        let lowest = q * fs / block_len.max(1) as f64;
+        let highest = fs / 2. / 2f64.powf(1. / bpo);
+        let first = (bpo * (lowest / TUNING_HZ).log2()).ceil() as i64;
+        let last = (bpo * (highest / TUNING_HZ).log2()).floor() as i64;
+        let freqs: Vec<f64> = (first..=last)
+            .map(|k| TUNING_HZ * 2f64.powf(k as f64 / bpo))
+            .collect();
+
+        let r2c = planner.plan_fft_forward(block_len);
+        let mut re_spectrum = r2c.make_output_vec();
+        let mut im_spectrum = r2c.make_output_vec();
+        let kernels = freqs
+            .iter()
+            .map(|freq| {
+                // at most the block length, since `freq` is at least `lowest`
+                let len = ((q * fs / freq).ceil() as usize).clamp(1, block_len);
+                let offset = (block_len - len) / 2;
+                let coefficients = window.coefficients(len);
+                // normalized by the window's sum rather than its length, which corrects for
+                // its coherent gain
+                let scale = coefficients.iter().sum::<f64>();
+
+                // the kernel is complex, so its real and imaginary parts are transformed
+                // separately and combined afterwards as re + i * im
+                let mut re = vec![0.; block_len];
+                let mut im = vec![0.; block_len];
+                for (i, w) in coefficients.iter().enumerate() {
+                    let phase = 2. * std::f64::consts::PI * freq * i as f64 / fs;
+                    re[offset + i] = w * phase.cos() / scale;
+                    im[offset + i] = w * phase.sin() / scale;
+                }
+                // only fails on mismatched buffer lengths, which the plan itself created
+                if r2c.process(&mut re, &mut re_spectrum).is_err()
+                    || r2c.process(&mut im, &mut im_spectrum).is_err()
+                {
+                    return vec![];
+                }
+
+                let spectrum: Vec<Complex<f64>> = re_spectrum
+                    .iter()
+                    .zip(&im_spectrum)
+                    .map(|(re, im)| Complex::new(re.re - im.im, re.im + im.re))
+                    .collect();
+                let max = spectrum.iter().map(|c| c.norm()).fold(0., f64::max);
+                spectrum
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| c.norm() >= max * SPARSITY)
+                    .map(|(j, c)| (j, c.conj()))
+                    .collect()
+            })
+            .collect();
+
+        ConstantQ {
+            block_len,
+            sample_rate,
+            bins_per_octave,
+            window,
+            freqs,
+            kernels,
+        }
+    }
+
+    /// Whether the kernels were computed for this configuration.
+    pub fn matches(
+        &self,
+        block_len: usize,
+        sample_rate: u32,
+        bins_per_octave: u32,
+        window: &WindowType,
+    ) -> bool {
+        self.block_len == block_len
+            && self.sample_rate == sample_rate
+            && self.bins_per_octave == bins_per_octave
+            && self.window == *window
+    }
+
+    /// Returns the center frequency of every bin in Hz, in ascending order.
+    pub fn freqs(&self) -> &[f64] {
+        &self.freqs
+    }
+
+    /// Returns the magnitude of every bin from `spectrum`, the unnormalized FFT of an
+    /// unwindowed block (as returned by realfft). A sine of amplitude `a` at a bin's frequency
+    /// gives `a / 2`, the same as a windowed FFT normalized by the block length and the
+    /// window's coherent gain.
+    pub fn magnitudes(&self, spectrum: &[Complex<f64>]) -> Vec<f64> {
+        self.kernels
+            .iter()
+            .map(|kernel| {
+                kernel
+                    .iter()
+                    .filter_map(|(j, k)| spectrum.get(*j).map(|x| x * k))
+                    .sum::<Complex<f64>>()
+                    .norm()
+                    / self.block_len as f64
+            })
+            .collect()
+    }
+
+    /// Resamples `magnitudes` of every bin onto `len` evenly spaced bins `freq_step` Hz apart,
+    /// interpolating on a logarithmic frequency axis. Bins below the lowest and above the
+    /// highest frequency repeat the nearest value.
+    pub fn to_linear(&self, magnitudes: &[f64], len: usize, freq_step: f64) -> Vec<f64> {
+        let Some((first, rest)) = self.freqs.split_first() else {
+            return vec![0.; len];
+        };
+        let bpo = f64::from(self.bins_per_octave.max(1));
+        let last = magnitudes.len().saturating_sub(1);
+        (0..len)
+            .map(|i| {
+                let freq = i as f64 * freq_step;
+                if freq <= *first || rest.is_empty() {
+                    return magnitudes.first().copied().unwrap_or(0.);
+                }
+                let pos = bpo * (freq / first).log2();
+                let below = (pos.floor() as usize).min(last);
+                let above = (below + 1).min(last);
+                let frac = (pos - below as f64).clamp(0., 1.);
+                magnitudes[below] * (1. - frac) + magnitudes[above] * frac
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+    const BLOCK_LEN: usize = 4096;
+
+    fn constant_q(bins_per_octave: u32) -> ConstantQ {
+        ConstantQ::new(
+            BLOCK_LEN,
+            SAMPLE_RATE,
+            bins_per_octave,
+            WindowType::Hanning,
+            &mut RealFftPlanner::new(),
+        )
+    }
+
+    #[test]
+    fn bins_are_tuned_and_evenly_spaced_per_octave() {
+        let cq = constant_q(12);
+        let freqs = cq.freqs();
+        assert!(freqs.iter().any(|f| (f - TUNING_HZ).abs() < 1e-9));
+        assert!(freqs[0] >= f64::from(SAMPLE_RATE) / BLOCK_LEN as f64);
+        assert!(*freqs.last().unwrap() <= f64::from(SAMPLE_RATE) / 2.);
+        for pair in freqs.windows(2) {
+            assert!((pair[1] / pair[0] - 2f64.powf(1. / 12.)).abs() < 1e-9);
+        }
+    }
+
+    // the FFT of a sine at `freq` Hz, as `magnitudes` expects it
+    fn tone_spectrum(freq: f64, amplitude: f64) -> Vec<Complex<f64>> {
+        let r2c = RealFftPlanner::new().plan_fft_forward(BLOCK_LEN);
+        let mut block: Vec<f64> = (0..BLOCK_LEN)
+            .map(|i| {
+                let phase = 2. * std::f64::consts::PI * freq * i as f64 / f64::from(SAMPLE_RATE);
+                amplitude * phase.sin()
+            })
+            .collect();
+        let mut spectrum = r2c.make_output_vec();
+        r2c.process(&mut block, &mut spectrum).unwrap();
+        spectrum
+    }
+
+    #[test]
+    fn a_tone_at_a_bin_reads_half_its_amplitude() {
+        let cq = constant_q(12);
+        for (bin, amplitude) in [(0, 1.), (30, 1.), (48, 0.5), (72, 0.25)] {
+            let freq = cq.freqs()[bin];
+            let magnitude = cq.magnitudes(&tone_spectrum(freq, amplitude))[bin];
+            assert!(
+                (magnitude / (amplitude / 2.) - 1.).abs() < 0.01,
+                "{freq:.1} Hz reads {magnitude}, not {}",
+                amplitude / 2.
+            );
+        }
+    }
+
+    #[test]
+    fn the_lowest_bin_fits_in_the_block() {
+        for bins_per_octave in [1, 12, 24, 96] {
+            let cq = constant_q(bins_per_octave);
+            let q = 1. / (2f64.powf(1. / f64::from(bins_per_octave)) - 1.);
+            let lowest = q * f64::from(SAMPLE_RATE) / BLOCK_LEN as f64;
+            let below = cq.freqs()[0] / 2f64.powf(1. / f64::from(bins_per_octave));
+            assert!(
+                below < lowest && lowest <= cq.freqs()[0],
+                "{bins_per_octave} bins per octave start at {} Hz",
+                cq.freqs()[0]
+            );
+        }
+    }
+
+    #[test]
+    fn low_and_high_bins_resolve_their_neighbours_alike() {
+        let cq = constant_q(12);
+        let last = cq.freqs().len() - 3;
+        for bin in [0, 1, last / 2, last] {
+            let magnitudes = cq.magnitudes(&tone_spectrum(cq.freqs()[bin], 1.));
+            let next = magnitudes[bin + 1] / magnitudes[bin];
+            let second = magnitudes[bin + 2] / magnitudes[bin];
+            // a Hann window's response about one of its own bins away, and near its first
+            // null at two, the same at every frequency when Q is constant
+            assert!((0.5..0.6).contains(&next), "bin {bin}: {next}");
+            assert!(second < 0.05, "bin {bin}: {second}");
+        }
+    }
+
+    #[test]
+    fn to_linear_interpolates_on_a_log_axis() {
+        let cq = constant_q(12);
+        let freqs = cq.freqs();
+        let magnitudes: Vec<f64> = (0..freqs.len()).map(|i| i as f64).collect();
+        let value_at = |freq: f64| cq.to_linear(&magnitudes, 2, freq)[1];
+
+        assert!((value_at(freqs[10]) - 10.).abs() < 1e-9);
+        // halfway between two bins on a log axis is their geometric mean
+        assert!((value_at((freqs[10] * freqs[11]).sqrt()) - 10.5).abs() < 1e-9);
+        // outside the bins the nearest value is repeated
+        assert_eq!(cq.to_linear(&magnitudes, 2, freqs[0] / 2.), vec![0., 0.]);
+        assert_eq!(
+            value_at(freqs[freqs.len() - 1] * 2.),
+            magnitudes[magnitudes.len() - 1]
+        );
+    }
+
+    #[test]
+    fn to_linear_without_bins() {
+        // a block this short resolves nothing below half the sample rate
+        let cq = ConstantQ::new(
+            2,
+            SAMPLE_RATE,
+            12,
+            WindowType::Hanning,
+            &mut RealFftPlanner::new(),
+        );
+        assert!(cq.freqs().is_empty());
+        assert_eq!(cq.to_linear(&[], 3, 100.), vec![0.; 3]);
+    }
+
+    #[test]
+    fn matches_its_configuration() {
+        let cq = constant_q(24);
+        assert!(cq.matches(BLOCK_LEN, SAMPLE_RATE, 24, &WindowType::Hanning));
+        assert!(!cq.matches(BLOCK_LEN, SAMPLE_RATE, 12, &WindowType::Hanning));
+        assert!(!cq.matches(BLOCK_LEN, 44100, 24, &WindowType::Hanning));
+        assert!(!cq.matches(BLOCK_LEN, SAMPLE_RATE, 24, &WindowType::Blackman));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{
+    app::Args,
+    error::{Error, Result},
+};
+
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CurveKind {
+    // only drawn, e.g. a target response
+    Reference,
+    // the spectrum fails where it rises above the curve
+    UpperMask,
+    // the spectrum fails where it drops below the curve
+    LowerMask,
+}
+
+// a piecewise linear frequency response, loaded from CSV lines of `freq_hz,db` or from JSON
+// holding either `[[freq, db], ...]` or `[{"freq": ..., "db": ...}, ...]`
+pub struct Curve {
+    pub name: String,
+    pub kind: CurveKind,
+    // sorted by frequency
+    pub points: Vec<(f64, f64)>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonPoint {
+    Pair(f64, f64),
+    Object { freq: f64, db: f64 },
+}
+
+impl Curve {
+    pub fn load(path: &Path, kind: CurveKind) -> Result<Curve> {
+        let invalid =
+            |message: String| Error::InvalidInput(format!("{}: {message}", path.display()));
+        let contents = fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        Curve::parse(name, &contents, json, kind).map_err(invalid)
+    }
+
+    // `contents` is JSON if `json` is set and CSV otherwise
+    pub fn parse(
+        name: String,
+        contents: &str,
+        json: bool,
+        kind: CurveKind,
+    ) -> std::result::Result<Curve, String> {
+        let mut points = if json {
+            serde_json::from_str::<Vec<JsonPoint>>(contents)
+                .map_err(|err| err.to_string())?
+                .into_iter()
+                .map(|point| match point {
+                    JsonPoint::Pair(freq, db) | JsonPoint::Object { freq, db } => (freq, db),
+                })
+                .collect()
+        } else {
+            parse_csv(contents)?
+        };
+
+        if points.len() < 2 {
+            return Err("a curve needs at least two points".into());
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(Curve { name, kind, points })
+    }
+
+    // interpolated on a logarithmic frequency axis, the way masks are usually specified, and
+    // `None` outside of the curve
+    pub fn level_at(&self, freq: f64) -> Option<f64> {
+        let (first, last) = (self.points.first()?, self.points.last()?);
+        if freq < first.0 || freq > last.0 {
+            return None;
+        }
+
+        let upper = self.points.partition_point(|(f, _)| *f < freq);
+        if upper == 0 {
+            return Some(first.1);
+        }
+        let (f0, db0) = self.points[upper - 1];
+        let (f1, db1) = self.points[upper];
+        let position = if f0 > 0. {
+            (freq / f0).ln() / (f1 / f0).ln()
+        } else {
+            (freq - f0) / (f1 - f0)
+        };
+        Some(db0 + (db1 - db0) * position)
+    }
+
+    // indices of the bins on the wrong side of a mask, always empty for a reference curve
+    pub fn violations(&self, bins: &[f64], freq_step: f64) -> Vec<usize> {
+        bins.iter()
+            .enumerate()
+            .filter(|(i, db)| {
+                let Some(limit) = self.level_at(freq_step * *i as f64) else {
+                    return false;
+                };
+                match self.kind {
+                    CurveKind::Reference => false,
+                    CurveKind::UpperMask => **db > limit,
+                    CurveKind::LowerMask => **db < limit,
+                }
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+// blank lines, lines starting with # and a header line are skipped
+fn parse_csv(contents: &str) -> std::result::Result<Vec<(f64, f64)>, String> {
+    let mut points = vec![];
+    let mut first = true;
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let header = std::mem::replace(&mut first, false);
+
+        let mut fields = line.split([',', ';', '\t']).map(str::trim);
+        let parsed = match (fields.next(), fields.next()) {
+            (Some(freq), Some(db)) => freq.parse::<f64>().ok().zip(db.parse::<f64>().ok()),
+            _ => None,
+        };
+        match parsed {
+            Some(point) => points.push(point),
+            None if header => continue,
+            None => return Err(format!("line {}: expected freq_hz,db", n + 1)),
+        }
+    }
+    Ok(points)
+}
+
+// loads every curve given on the command line
+pub fn load_all(args: &Args) -> Result<Vec<Curve>> {
+    let kinds = [
+        (&args.curve, CurveKind::Reference),
+        (&args.upper_mask, CurveKind::UpperMask),
+        (&args.lower_mask, CurveKind::LowerMask),
+    ];
+    let mut curves = vec![];
+    for (paths, kind) in kinds {
+        for path in paths {
+            curves.push(Curve::load(path, kind)?);
+        }
+    }
+    Ok(curves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(kind: CurveKind, points: &[(f64, f64)]) -> Curve {
+        Curve {
+            name: "test".into(),
+            kind,
+            points: points.to_vec(),
+        }
+    }
+
+    #[test]
+    fn parse_valid_curves() {
+        let cases = [
+            ("100,-10\n1000,-20", false),
+            ("freq_hz,db\n# comment\n\n100, -10\n1000 ,-20\n", false),
+            ("100;-10\n1000\t-20", false),
+            // sorted by frequency whatever the order in the file
+            ("1000,-20\n100,-10", false),
+            ("[[100, -10], [1000, -20]]", true),
+            (
+                r#"[{"freq": 1000, "db": -20}, {"freq": 100, "db": -10}]"#,
+                true,
+            ),
+        ];
+        for (contents, json) in cases {
+            let curve = Curve::parse("test".into(), contents, json, CurveKind::Reference)
+                .unwrap_or_else(|err| panic!("{contents:?}: {err}"));
+            assert_eq!(curve.points, [(100., -10.), (1000., -20.)], "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn parse_malformed_curves() {
+        let cases = [
+            ("100,-10\n1000,abc", false, "line 2: expected freq_hz,db"),
+            ("100,-10\n1000", false, "line 2: expected freq_hz,db"),
+            (
+                "freq_hz,db\n100,-10",
+                false,
+                "a curve needs at least two points",
+            ),
+            ("", false, "a curve needs at least two points"),
+            ("[[100, -10]]", true, "a curve needs at least two points"),
+        ];
+        for (contents, json, error) in cases {
+            let result = Curve::parse("test".into(), contents, json, CurveKind::Reference);
+            assert_eq!(result.err().as_deref(), Some(error), "{contents:?}");
+        }
+        assert!(Curve::parse("test".into(), "[[100]]", true, CurveKind::Reference).is_err());
+        assert!(Curve::parse("test".into(), "100,-10", true, CurveKind::Reference).is_err());
+    }
+
+    #[test]
+    fn load_names_the_file_in_errors() {
+        let path = std::env::temp_dir().join("audiolyzer-missing-curve.csv");
+        let Err(err) = Curve::load(&path, CurveKind::UpperMask) else {
+            panic!("loaded a missing file");
+        };
+        assert!(
+            err.to_string().contains(&path.display().to_string()),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn level_between_points_is_interpolated_on_a_log_axis() {
+        let curve = curve(
+            CurveKind::Reference,
+            &[(100., 0.), (1000., -20.), (10000., -20.)],
+        );
+        let cases = [
+            (100., Some(0.)),
+            (1000., Some(-20.)),
+            (10f64.powf(2.5), Some(-10.)),
+            (5000., Some(-20.)),
+            (10000., Some(-20.)),
+            (99., None),
+            (10001., None),
+        ];
+        for (freq, level) in cases {
+            match (curve.level_at(freq), level) {
+                (Some(got), Some(want)) => assert!((got - want).abs() < 1e-9, "{freq} Hz: {got}"),
+                (got, want) => assert_eq!(got, want, "{freq} Hz"),
+            }
+        }
+    }
+
+    #[test]
+    fn level_from_zero_hz_is_interpolated_linearly() {
+        let curve = curve(CurveKind::Reference, &[(0., 0.), (100., -10.)]);
+        assert_eq!(curve.level_at(50.), Some(-5.));
+    }
+
+    #[test]
+    fn violations() {
+        let points = [(100., -20.), (1000., -20.)];
+        // bins 100 Hz apart, from 0 to 1200 Hz
+        let bins = [
+            -10., -30., -10., -30., -20., -30., -10., -30., -10., -30., -10., -10., -10.,
+        ];
+        let cases = [
+            (CurveKind::UpperMask, vec![2, 6, 8, 10]),
+            (CurveKind::LowerMask, vec![1, 3, 5, 7, 9]),
+            (CurveKind::Reference, vec![]),
+        ];
+        for (kind, violations) in cases {
+            assert_eq!(
+                curve(kind, &points).violations(&bins, 100.),
+                violations,
+                "{kind:?}"
+            );
+        }
+    }
+}
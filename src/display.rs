@@ -1,75 +1,236 @@
-use ratatui::{
-    style::Color,
-    widgets::canvas::{Context, Line, Points},
-};
+//! Strategies drawing a normalized spectrum onto a [`Plot`](crate::plot::Plot).
 
+use crate::plot::Painter;
+
+use std::{env, str::FromStr};
+
+use ratatui::style::Color;
+
+/// How many colors the terminal can show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// 24-bit RGB.
+    TrueColor,
+    /// The xterm 256 color palette.
+    Ansi256,
+    /// The 16 basic ANSI colors.
+    Ansi16,
+}
+
+// the usual xterm values of the 16 basic colors
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+impl ColorDepth {
+    /// Guesses the depth from `COLORTERM` and `TERM`, the way most terminal programs do.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        if env::var("TERM").is_ok_and(|term| term.contains("256color")) {
+            return ColorDepth::Ansi256;
+        }
+        ColorDepth::Ansi16
+    }
+
+    /// Returns the color closest to `rgb` that this depth can show.
+    pub fn color(&self, (r, g, b): (u8, u8, u8)) -> Color {
+        match self {
+            ColorDepth::TrueColor => Color::Rgb(r, g, b),
+            ColorDepth::Ansi256 => {
+                // the 6x6x6 cube starts at 16, the 24 step gray ramp at 232
+                let level = |c: u8| (f64::from(c) / 255. * 5.).round() as u8;
+                let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+                let gray = (f64::from(r) + f64::from(g) + f64::from(b)) / 3.;
+                let gray_idx = ((gray - 8.) / 10.).round().clamp(0., 23.) as u8;
+                let gray_value = 8. + 10. * f64::from(gray_idx);
+                let cube_value = |c: u8| f64::from(level(c)) * 51.;
+                let cube_error = distance((r, g, b), (cube_value(r), cube_value(g), cube_value(b)));
+                let gray_error = distance((r, g, b), (gray_value, gray_value, gray_value));
+                Color::Indexed(if gray_error < cube_error {
+                    232 + gray_idx
+                } else {
+                    cube
+                })
+            }
+            ColorDepth::Ansi16 => {
+                let error = |(cr, cg, cb): (u8, u8, u8)| {
+                    distance((r, g, b), (f64::from(cr), f64::from(cg), f64::from(cb)))
+                };
+                ANSI16
+                    .iter()
+                    .min_by(|x, y| error(x.1).total_cmp(&error(y.1)))
+                    .map(|(color, _)| *color)
+                    .unwrap_or(Color::White)
+            }
+        }
+    }
+
+    /// Converts an RGB color to one this depth can show, other colors are kept as they are.
+    pub fn adapt(&self, color: Color) -> Color {
+        match color {
+            Color::Rgb(r, g, b) => self.color((r, g, b)),
+            _ => color,
+        }
+    }
+}
+
+fn distance((r, g, b): (u8, u8, u8), (r2, g2, b2): (f64, f64, f64)) -> f64 {
+    (f64::from(r) - r2).powi(2) + (f64::from(g) - g2).powi(2) + (f64::from(b) - b2).powi(2)
+}
+
+impl FromStr for ColorDepth {
+    type Err = String;
+
+    /// Parses `truecolor` (or `24bit`), `256` or `16`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "truecolor" | "24bit" => Ok(ColorDepth::TrueColor),
+            "256" => Ok(ColorDepth::Ansi256),
+            "16" => Ok(ColorDepth::Ansi16),
+            _ => Err(format!("unknown color depth: {s}")),
+        }
+    }
+}
+
+/// Evenly spaced RGB color stops, interpolated linearly in between.
+#[derive(Clone, Debug)]
+pub struct Gradient(pub Vec<(u8, u8, u8)>);
+
+impl Gradient {
+    /// Returns the color at `t` in `[0, 1]`, values outside are clamped.
+    pub fn at(&self, t: f64) -> (u8, u8, u8) {
+        match self.0.len() {
+            0 => (255, 255, 255),
+            1 => self.0[0],
+            len => {
+                let position = t.clamp(0., 1.) * (len - 1) as f64;
+                let idx = (position.floor() as usize).min(len - 2);
+                let frac = position - idx as f64;
+                let (a, b) = (self.0[idx], self.0[idx + 1]);
+                let mix = |x: u8, y: u8| {
+                    (f64::from(x) + (f64::from(y) - f64::from(x)) * frac).round() as u8
+                };
+                (mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+            }
+        }
+    }
+}
+
+/// How the bins of a spectrum are colored.
+#[derive(Clone, Debug)]
+pub enum Coloring {
+    /// Every bin gets the same color.
+    Solid(Color),
+    /// Quiet bins take the start of the gradient, loud ones its end.
+    ByLevel(Gradient),
+    /// The gradient runs from 20 Hz to 20 kHz on a logarithmic axis.
+    ByFrequency(Gradient),
+}
+
+/// Picks the color of every bin, limited to what the terminal can show.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    /// How the bins are colored.
+    pub coloring: Coloring,
+    /// The colors available in the terminal.
+    pub depth: ColorDepth,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            coloring: Coloring::Solid(Color::White),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+}
+
+impl Palette {
+    /// Returns the color of a bin at `freq` Hz with a `level` normalized to `[0, 1]`.
+    pub fn color(&self, freq: f64, level: f64) -> Color {
+        match &self.coloring {
+            Coloring::Solid(color) => self.depth.adapt(*color),
+            Coloring::ByLevel(gradient) => self.depth.color(gradient.at(level)),
+            Coloring::ByFrequency(gradient) => {
+                let position = (freq.max(20.) / 20.).log10() / 3.;
+                self.depth.color(gradient.at(position))
+            }
+        }
+    }
+}
+
+/// Draws a spectrum onto a [`Plot`](crate::plot::Plot) whose x axis is frequency in Hz and y
+/// axis is level in `[0, 1]`.
 pub trait DisplayStrategy {
-    fn render(&self, ctx: &mut Context, bins: &Vec<f64>, freq_step: f64);
+    /// Draws `bins`, already normalized to `[0, 1]`, where bin `i` sits at `i * freq_step` Hz,
+    /// in the colors picked by `palette`.
+    fn render(&self, painter: &mut Painter, bins: &[f64], freq_step: f64, palette: &Palette);
 }
 
+/// Draws every bin as a vertical line.
 pub struct DiscreteStrategy;
 
-// creates display data vector of lines for the canvas widget
 impl DisplayStrategy for DiscreteStrategy {
-    fn render(&self, ctx: &mut Context, bins: &Vec<f64>, freq_step: f64) {
-        let mut display_vec = vec![];
-        for bin in bins.iter().enumerate() {
-            if *bin.1 != 0f64 {
-                display_vec.push(Line {
-                    x1: freq_step * bin.0 as f64,
-                    y1: 0.0,
-                    x2: freq_step * bin.0 as f64,
-                    y2: *bin.1,
-                    color: Color::White,
-                });
+    fn render(&self, painter: &mut Painter, bins: &[f64], freq_step: f64, palette: &Palette) {
+        for (idx, level) in bins.iter().enumerate() {
+            if *level != 0f64 {
+                let freq = freq_step * idx as f64;
+                painter.line(freq, 0.0, freq, *level, palette.color(freq, *level));
             }
         }
-
-        for line in &display_vec {
-            ctx.draw(line);
-        }
     }
 }
 
+/// Draws every bin as a single point.
 pub struct PointStrategy;
 
-// creates display data vector of points for the canvas widget
 impl DisplayStrategy for PointStrategy {
-    fn render(&self, ctx: &mut Context, bins: &Vec<f64>, freq_step: f64) {
-        let mut display_vec = vec![];
-        for bin in bins.iter().enumerate() {
-            if *bin.1 != 0f64 {
-                display_vec.push((freq_step * bin.0 as f64, *bin.1));
+    fn render(&self, painter: &mut Painter, bins: &[f64], freq_step: f64, palette: &Palette) {
+        for (idx, level) in bins.iter().enumerate() {
+            if *level != 0f64 {
+                let freq = freq_step * idx as f64;
+                painter.point(freq, *level, palette.color(freq, *level));
             }
         }
-
-        ctx.draw(&Points {
-            coords: &display_vec,
-            color: Color::White,
-        });
     }
 }
 
-pub struct LineStrategy;
+/// Connects neighbouring bins with lines.
+pub struct LineStrategy {
+    /// Also fills the area below the lines, making an area graph.
+    pub filled: bool,
+}
 
-// creates display data vector of lines as an area graph for the canvas widget
 impl DisplayStrategy for LineStrategy {
-    fn render(&self, ctx: &mut Context, bins: &Vec<f64>, freq_step: f64) {
-        let mut display_vec: Vec<Line> = vec![];
-        for idx in 0..bins.len() - 1 {
-            if bins[idx] != 0f64 || bins[idx + 1] != 0f64 {
-                display_vec.push(Line {
-                    x1: freq_step * idx as f64,
-                    y1: bins[idx],
-                    x2: freq_step * (idx + 1) as f64,
-                    y2: bins[idx + 1],
-                    color: Color::White,
-                });
+    fn render(&self, painter: &mut Painter, bins: &[f64], freq_step: f64, palette: &Palette) {
+        for (idx, pair) in bins.windows(2).enumerate() {
+            if pair[0] == 0f64 && pair[1] == 0f64 {
+                continue;
             }
-        }
-
-        for line in &display_vec {
-            ctx.draw(line);
+            let (x1, x2) = (freq_step * idx as f64, freq_step * (idx + 1) as f64);
+            let color = palette.color(freq_step * (idx as f64 + 0.5), pair[0].max(pair[1]));
+            if self.filled {
+                painter.area(x1, pair[0], x2, pair[1], color);
+            }
+            painter.line(x1, pair[0], x2, pair[1], color);
         }
     }
 }
@@ -87,15 +248,119 @@ impl DisplayStrategy for LineStrategy {
 //     display_vec
 // }
 
+/// Looks up display strategies by name.
 pub struct DisplayStrategyFactory;
 
 impl DisplayStrategyFactory {
+    /// Names of all strategies, in the order they are cycled through.
+    pub const NAMES: [&'static str; 4] = ["DISCRETE", "POINT", "LINE", "AREA"];
+
+    /// Returns the strategy for `DISCRETE`, `POINT`, `LINE` or `AREA` (a filled line), falling
+    /// back to discrete.
     pub fn get_display_strategy(strategy: &str) -> Box<dyn DisplayStrategy> {
         match strategy {
             "DISCRETE" => Box::new(DiscreteStrategy),
             "POINT" => Box::new(PointStrategy),
-            "LINE" => Box::new(LineStrategy),
+            "LINE" => Box::new(LineStrategy { filled: false }),
+            "AREA" => Box::new(LineStrategy { filled: true }),
             _ => Box::new(DiscreteStrategy),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn true_color_keeps_the_exact_color() {
+        assert_eq!(
+            ColorDepth::TrueColor.color((255, 128, 0)),
+            Color::Rgb(255, 128, 0)
+        );
+    }
+
+    #[test]
+    fn ansi256_picks_the_cube_or_the_gray_ramp() {
+        let depth = ColorDepth::Ansi256;
+        // pure colors are corners of the 6x6x6 cube
+        assert_eq!(depth.color((255, 0, 0)), Color::Indexed(196));
+        assert_eq!(depth.color((0, 0, 0)), Color::Indexed(16));
+        assert_eq!(depth.color((255, 255, 255)), Color::Indexed(231));
+        // a middle gray is closer to the ramp than to the cube
+        assert_eq!(depth.color((128, 128, 128)), Color::Indexed(244));
+    }
+
+    #[test]
+    fn ansi16_picks_the_closest_basic_color() {
+        let depth = ColorDepth::Ansi16;
+        assert_eq!(depth.color((250, 10, 10)), Color::LightRed);
+        assert_eq!(depth.color((200, 0, 0)), Color::Red);
+        assert_eq!(depth.color((120, 120, 130)), Color::DarkGray);
+        assert_eq!(depth.color((0, 0, 0)), Color::Black);
+    }
+
+    #[test]
+    fn only_rgb_colors_are_adapted() {
+        assert_eq!(
+            ColorDepth::Ansi16.adapt(Color::Indexed(100)),
+            Color::Indexed(100)
+        );
+        assert_eq!(ColorDepth::Ansi16.adapt(Color::Cyan), Color::Cyan);
+        assert_eq!(ColorDepth::Ansi16.adapt(Color::Rgb(0, 0, 240)), Color::Blue);
+    }
+
+    #[test]
+    fn parsing_depths() {
+        assert_eq!("TrueColor".parse(), Ok(ColorDepth::TrueColor));
+        assert_eq!("24bit".parse(), Ok(ColorDepth::TrueColor));
+        assert_eq!("256".parse(), Ok(ColorDepth::Ansi256));
+        assert_eq!("16".parse(), Ok(ColorDepth::Ansi16));
+        assert!("8".parse::<ColorDepth>().is_err());
+    }
+
+    #[test]
+    fn gradients_interpolate_between_stops() {
+        let gradient = Gradient(vec![(0, 0, 0), (200, 100, 0), (200, 200, 200)]);
+        assert_eq!(gradient.at(0.), (0, 0, 0));
+        assert_eq!(gradient.at(0.25), (100, 50, 0));
+        assert_eq!(gradient.at(0.5), (200, 100, 0));
+        assert_eq!(gradient.at(0.75), (200, 150, 100));
+        assert_eq!(gradient.at(1.), (200, 200, 200));
+        // clamped outside of [0, 1]
+        assert_eq!(gradient.at(-1.), (0, 0, 0));
+        assert_eq!(gradient.at(2.), (200, 200, 200));
+    }
+
+    #[test]
+    fn degenerate_gradients() {
+        assert_eq!(Gradient(vec![]).at(0.3), (255, 255, 255));
+        assert_eq!(Gradient(vec![(1, 2, 3)]).at(0.3), (1, 2, 3));
+    }
+
+    #[test]
+    fn palettes_color_by_level_or_frequency() {
+        let gradient = Gradient(vec![(0, 0, 0), (255, 255, 255)]);
+        let by_level = Palette {
+            coloring: Coloring::ByLevel(gradient.clone()),
+            depth: ColorDepth::TrueColor,
+        };
+        assert_eq!(by_level.color(1000., 0.), Color::Rgb(0, 0, 0));
+        assert_eq!(by_level.color(1000., 1.), Color::Rgb(255, 255, 255));
+
+        // 20 Hz to 20 kHz, so 632 Hz is halfway on the logarithmic axis
+        let by_frequency = Palette {
+            coloring: Coloring::ByFrequency(gradient),
+            depth: ColorDepth::TrueColor,
+        };
+        assert_eq!(by_frequency.color(10., 1.), Color::Rgb(0, 0, 0));
+        assert_eq!(by_frequency.color(632.5, 1.), Color::Rgb(128, 128, 128));
+        assert_eq!(by_frequency.color(40000., 0.), Color::Rgb(255, 255, 255));
+
+        let solid = Palette {
+            coloring: Coloring::Solid(Color::Rgb(255, 0, 0)),
+            depth: ColorDepth::Ansi16,
+        };
+        assert_eq!(solid.color(1000., 0.5), Color::LightRed);
+    }
+}
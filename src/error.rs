@@ -0,0 +1,136 @@
+use std::io;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("audio host unavailable: {0}")]
+    HostUnavailable(String),
+
+    #[error("input device not found: {0}")]
+    DeviceNotFound(String),
+
+    #[error("unsupported stream config: {0}")]
+    UnsupportedConfig(String),
+
+    #[error("stream error: {0}")]
+    Stream(String),
+
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Wav(#[from] hound::Error),
+
+    #[error(transparent)]
+    Image(#[from] png::EncodingError),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<cpal::BuildStreamError> for Error {
+    fn from(err: cpal::BuildStreamError) -> Self {
+        match err {
+            cpal::BuildStreamError::StreamConfigNotSupported => {
+                Error::UnsupportedConfig(err.to_string())
+            }
+            cpal::BuildStreamError::DeviceNotAvailable => Error::DeviceNotFound(err.to_string()),
+            _ => Error::Stream(err.to_string()),
+        }
+    }
+}
+
+impl From<cpal::PlayStreamError> for Error {
+    fn from(err: cpal::PlayStreamError) -> Self {
+        Error::Stream(err.to_string())
+    }
+}
+
+impl From<cpal::StreamError> for Error {
+    fn from(err: cpal::StreamError) -> Self {
+        Error::Stream(err.to_string())
+    }
+}
+
+impl From<cpal::DevicesError> for Error {
+    fn from(err: cpal::DevicesError) -> Self {
+        Error::DeviceNotFound(err.to_string())
+    }
+}
+
+impl From<cpal::SupportedStreamConfigsError> for Error {
+    fn from(err: cpal::SupportedStreamConfigsError) -> Self {
+        match err {
+            cpal::SupportedStreamConfigsError::DeviceNotAvailable => {
+                Error::DeviceNotFound(err.to_string())
+            }
+            _ => Error::UnsupportedConfig(err.to_string()),
+        }
+    }
+}
+
+impl From<cpal::HostUnavailable> for Error {
+    fn from(err: cpal::HostUnavailable) -> Self {
+        Error::HostUnavailable(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unplugged_devices_are_reported_as_not_found() {
+        let err = Error::from(cpal::BuildStreamError::DeviceNotAvailable);
+        assert!(matches!(err, Error::DeviceNotFound(_)), "{err:?}");
+        assert!(err.to_string().starts_with("input device not found: "));
+
+        let err = Error::from(cpal::SupportedStreamConfigsError::DeviceNotAvailable);
+        assert!(matches!(err, Error::DeviceNotFound(_)), "{err:?}");
+    }
+
+    #[test]
+    fn rejected_configs_are_reported_as_unsupported() {
+        let err = Error::from(cpal::BuildStreamError::StreamConfigNotSupported);
+        assert!(matches!(err, Error::UnsupportedConfig(_)), "{err:?}");
+
+        let err = Error::from(cpal::SupportedStreamConfigsError::InvalidArgument);
+        assert!(matches!(err, Error::UnsupportedConfig(_)), "{err:?}");
+    }
+
+    #[test]
+    fn other_stream_failures_are_stream_errors() {
+        let backend = cpal::BackendSpecificError {
+            description: "xrun storm".into(),
+        };
+        let err = Error::from(cpal::BuildStreamError::BackendSpecific {
+            err: backend.clone(),
+        });
+        assert_eq!(
+            err.to_string(),
+            "stream error: A backend-specific error has occurred: xrun storm"
+        );
+
+        let err = Error::from(cpal::StreamError::DeviceNotAvailable);
+        assert!(matches!(err, Error::Stream(_)), "{err:?}");
+        let err = Error::from(cpal::PlayStreamError::BackendSpecific { err: backend });
+        assert!(matches!(err, Error::Stream(_)), "{err:?}");
+    }
+
+    #[test]
+    fn io_errors_keep_their_message() {
+        let err = Error::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(err.to_string(), "no such file");
+        let err = Error::from(hound::Error::FormatError("not a WAVE file"));
+        assert!(matches!(err, Error::Wav(_)), "{err:?}");
+    }
+}
@@ -1,15 +1,26 @@
-use std::io::Write;
+//! The FFT engine turning blocks of samples into a smoothed spectrum in dB.
 
-use apodize::CosineWindowIter;
+pub use crate::window::{Correction, WindowType};
+use crate::{constant_q::ConstantQ, window::coherent_gain};
 
-#[derive(Clone, Debug)]
-pub enum WindowType {
-    Hanning,
-    Hamming,
-    Blackman,
-    Nuttall,
+use realfft::RealFftPlanner;
+
+/// How a block of samples is turned into a spectrum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    /// One FFT over the whole windowed block, every bin as wide as every other.
+    Fft,
+    /// A [constant-Q transform](crate::constant_q) with this many bins per octave, resampled
+    /// onto the bins of the FFT so it can be used in place of one.
+    ConstantQ(u32),
 }
 
+/// Windows blocks of samples, transforms them and keeps an exponentially smoothed magnitude
+/// spectrum in dB.
+///
+/// A frame is processed by calling [`set_src_buf`](FFTEngine::set_src_buf),
+/// [`apply_window`](FFTEngine::apply_window) and [`apply_fft`](FFTEngine::apply_fft) in order,
+/// after which [`get_bins`](FFTEngine::get_bins) returns the updated spectrum.
 pub struct FFTEngine {
     prev_data: Vec<f64>,
     curr_data: Vec<f32>,
@@ -17,10 +28,25 @@ pub struct FFTEngine {
     smoothing_base: f64,
     processed_values: Vec<f64>,
     window_fn: WindowType,
-    logger: std::fs::File,
+    // the coefficients of `window_fn` for the length of the latest block
+    window: Vec<f64>,
+    main_lobe_bins: f64,
+    correction: Correction,
+    // what the magnitudes of the current block are divided by, 1 until it was windowed
+    window_gain: f64,
+    transform: Transform,
+    zero_pad: usize,
+    // computed on the first constant-Q frame and kept until the configuration changes
+    constant_q: Option<ConstantQ>,
+    // the planner caches plans per length, so keeping it around avoids replanning every frame
+    planner: RealFftPlanner<f64>,
 }
 
 impl FFTEngine {
+    /// Creates an engine whose spectrum holds `bins` values, which has to be at least
+    /// `n / 2 + 1` for blocks of `n` samples, or `n * factor / 2 + 1` with
+    /// [`set_zero_pad`](FFTEngine::set_zero_pad). `smoothing_base` in `[0, 1)` is the weight given
+    /// to the previous frame, 0 disables smoothing.
     pub fn new(sample_rate: u32, bins: usize, smoothing_base: f64, window_fn: WindowType) -> Self {
         FFTEngine {
             prev_data: vec![0.; bins],
@@ -28,83 +54,240 @@ impl FFTEngine {
             processed_values: vec![-85.; bins],
             sample_rate,
             smoothing_base,
+            main_lobe_bins: window_fn.main_lobe_bins(),
             window_fn,
-            logger: std::fs::File::create("txt/output.txt").unwrap(),
+            window: vec![],
+            correction: Correction::Amplitude,
+            window_gain: 1.,
+            transform: Transform::Fft,
+            zero_pad: 1,
+            constant_q: None,
+            planner: RealFftPlanner::new(),
         }
     }
 
     // GETTERS AND SETTERS
 
+    /// Changes the window function used by [`apply_window`](FFTEngine::apply_window).
     pub fn set_window(&mut self, window_fn: WindowType) {
+        self.main_lobe_bins = window_fn.main_lobe_bins();
         self.window_fn = window_fn;
+        self.window.clear();
     }
 
+    /// Returns the current window function.
     pub fn get_window(&self) -> WindowType {
         self.window_fn.clone()
     }
 
+    /// Returns [`WindowType::main_lobe_bins`] of the current window.
+    pub fn get_main_lobe_bins(&self) -> f64 {
+        self.main_lobe_bins
+    }
+
+    /// Changes what the levels of the spectrum are corrected for, so they read the same with
+    /// every window. [`Correction::Amplitude`] by default.
+    pub fn set_correction(&mut self, correction: Correction) {
+        self.correction = correction;
+    }
+
+    /// Returns what the levels of the spectrum are corrected for.
+    pub fn get_correction(&self) -> Correction {
+        self.correction
+    }
+
+    /// Changes how [`apply_fft`](FFTEngine::apply_fft) turns blocks into a spectrum.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    /// Returns how blocks are turned into a spectrum.
+    pub fn get_transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Pads every windowed block with zeros to `factor` times its length before the FFT, so
+    /// the spectrum holds `n * factor / 2 + 1` bins spaced `sample_rate / (n * factor)` apart.
+    /// This interpolates between the bins of the unpadded block, it doesn't separate tones any
+    /// better; that still takes a longer block. 1 disables padding.
+    pub fn set_zero_pad(&mut self, factor: usize) {
+        self.zero_pad = factor.max(1);
+    }
+
+    /// Returns the factor blocks are padded to before the FFT.
+    pub fn get_zero_pad(&self) -> usize {
+        self.zero_pad
+    }
+
+    /// Sets the block of samples for the next frame, an empty block keeps the previous one.
     pub fn set_src_buf(&mut self, samples: &[f32]) {
         if samples.is_empty() {
             return;
         }
         self.curr_data = samples.to_owned();
-        self.log(format!("Curr_data.len {:?}\n", self.curr_data.len()));
+        self.window_gain = 1.;
+        log::trace!("block of {} samples", self.curr_data.len());
     }
 
+    /// Returns the current block of samples, windowed once [`apply_window`](FFTEngine::apply_window) ran.
     pub fn get_src_buf(&self) -> Vec<f32> {
         self.curr_data.clone()
     }
 
+    /// Changes the weight given to the previous frame, see [`new`](FFTEngine::new).
+    pub fn set_smoothing(&mut self, smoothing_base: f64) {
+        self.smoothing_base = smoothing_base;
+    }
+
+    /// Returns the weight given to the previous frame.
+    pub fn get_smoothing(&self) -> f64 {
+        self.smoothing_base
+    }
+
+    /// Resizes the spectrum to `bins` values and forgets all previous frames, needed whenever
+    /// the block length changes since every bin then covers a different frequency.
+    pub fn reset(&mut self, bins: usize) {
+        self.prev_data = vec![0.; bins];
+        self.processed_values = vec![-85.; bins];
+        self.curr_data.clear();
+    }
+
+    /// Changes the sample rate of the analyzed samples, e.g. after switching devices.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
     // READ-ONLY GETTERS
 
+    /// Returns the smoothed spectrum in dB, bin `i` is centered on
+    /// `i * sample_rate / (n * factor)` for the factor of [`set_zero_pad`](FFTEngine::set_zero_pad).
     pub fn get_bins(&self) -> Vec<f64> {
         // remove the first value because that is the DC component of FFT and has no frequency information
         self.processed_values.to_vec()
     }
 
+    /// Returns the sample rate of the analyzed samples.
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     // HELPER FUNCTIONS
 
+    /// Multiplies the current block with the window function, whose attenuation is then
+    /// corrected for as set by [`set_correction`](FFTEngine::set_correction). With
+    /// [`Transform::ConstantQ`] the block is left as it is, every bin of the transform
+    /// windows it on its own.
     pub fn apply_window(&mut self) {
-        if !(1 < self.curr_data.len()) {
+        if self.curr_data.len() <= 1 || self.transform != Transform::Fft {
             return;
         }
 
-        let window_fn: CosineWindowIter = match self.window_fn {
-            WindowType::Hanning => apodize::hanning_iter(self.curr_data.len()),
-            WindowType::Blackman => apodize::blackman_iter(self.curr_data.len()),
-            WindowType::Hamming => apodize::hamming_iter(self.curr_data.len()),
-            WindowType::Nuttall => apodize::nuttall_iter(self.curr_data.len()),
-        };
-        let window = window_fn.map(|f| f as f32).collect::<Vec<f32>>();
+        if self.window.len() != self.curr_data.len() {
+            self.window = self.window_fn.coefficients(self.curr_data.len());
+        }
+        self.window_gain = self.correction.gain(&self.window);
 
-        self.curr_data = window
+        self.curr_data = self
+            .window
             .iter()
             .zip(self.curr_data.iter())
-            .map(|f| f.0 * f.1)
+            .map(|f| *f.0 as f32 * f.1)
             .collect();
     }
 
+    /// Transforms the current block and folds its magnitudes into the smoothed spectrum.
     pub fn apply_fft(&mut self) {
-        let mut real_planner = realfft::RealFftPlanner::<f64>::new();
-        let r2c = real_planner.plan_fft_forward(self.curr_data.len());
+        if let Transform::ConstantQ(bins_per_octave) = self.transform {
+            return self.apply_constant_q(bins_per_octave);
+        }
+        let r2c = self
+            .planner
+            .plan_fft_forward(self.curr_data.len() * self.zero_pad);
         // make input and output vectors
         let mut spectrum = r2c.make_output_vec();
         let mut arr: Vec<f64> = self.curr_data.iter().map(|val| *val as f64).collect();
+        arr.resize(r2c.len(), 0.);
 
-        r2c.process(&mut arr[..], &mut spectrum).unwrap();
+        // only fails on mismatched buffer lengths, which the plan itself created
+        if r2c.process(&mut arr[..], &mut spectrum).is_err() {
+            return;
+        }
 
-        self.log(format!("spectrum.len {:?}\n", spectrum.len()));
+        log::trace!("spectrum of {} bins", spectrum.len());
 
+        // the padding adds no energy, so the magnitudes are normalized by the samples only
+        let scale = self.curr_data.len() as f64 * self.window_gain;
         let magnitudes = spectrum
             .iter()
-            .map(|complex| complex.norm())
+            .map(|complex| complex.norm() / scale)
             .collect::<Vec<f64>>();
 
-        for (i, magnitude) in magnitudes.iter().enumerate() {
-            let y_value_raw = magnitude / (self.curr_data.len() as f64);
+        self.fold(&magnitudes);
+    }
+
+    // the block is transformed once, unwindowed, and every constant-Q bin picks what it needs
+    // from that spectrum
+    fn apply_constant_q(&mut self, bins_per_octave: u32) {
+        let len = self.curr_data.len();
+        if len == 0 {
+            return;
+        }
+        let r2c = self.planner.plan_fft_forward(len);
+        let mut spectrum = r2c.make_output_vec();
+        let mut arr: Vec<f64> = self.curr_data.iter().map(|val| *val as f64).collect();
+        // only fails on mismatched buffer lengths, which the plan itself created
+        if r2c.process(&mut arr[..], &mut spectrum).is_err() {
+            return;
+        }
+
+        let constant_q = match self.constant_q.take() {
+            Some(constant_q)
+                if constant_q.matches(len, self.sample_rate, bins_per_octave, &self.window_fn) =>
+            {
+                constant_q
+            }
+            _ => {
+                log::debug!(
+                    "constant-Q kernels for {len} samples, {bins_per_octave} bins per octave"
+                );
+                ConstantQ::new(
+                    len,
+                    self.sample_rate,
+                    bins_per_octave,
+                    self.window_fn.clone(),
+                    &mut self.planner,
+                )
+            }
+        };
+        // the kernels read tones corrected for the amplitude, which is turned into the
+        // correction that is set so both transforms read the same levels
+        if self.window.len() != len {
+            self.window = self.window_fn.coefficients(len);
+        }
+        let correction = coherent_gain(&self.window) / self.correction.gain(&self.window);
+
+        // padding only makes the bins the result is spread over finer
+        let padded_len = len * self.zero_pad;
+        let freq_step = f64::from(self.sample_rate) / padded_len as f64;
+        let magnitudes: Vec<f64> = constant_q
+            .to_linear(
+                &constant_q.magnitudes(&spectrum),
+                padded_len / 2 + 1,
+                freq_step,
+            )
+            .iter()
+            .map(|magnitude| magnitude * correction)
+            .collect();
+        self.constant_q = Some(constant_q);
 
+        self.fold(&magnitudes);
+    }
+
+    // smooths the magnitudes of the latest block into the spectrum
+    fn fold(&mut self, magnitudes: &[f64]) {
+        for (i, magnitude) in magnitudes.iter().enumerate() {
             self.prev_data[i] =
-                self.prev_data[i] * self.smoothing_base + y_value_raw * (1. - self.smoothing_base);
+                self.prev_data[i] * self.smoothing_base + magnitude * (1. - self.smoothing_base);
             self.processed_values[i] = self.linear_to_db(self.prev_data[i]);
         }
     }
@@ -116,8 +299,145 @@ impl FFTEngine {
             20f64 * value.log10()
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+    const LEN: usize = 4096;
+
+    fn sine(freq: f64, amplitude: f64) -> Vec<f32> {
+        (0..LEN)
+            .map(|i| {
+                let phase = 2. * std::f64::consts::PI * freq * i as f64 / f64::from(SAMPLE_RATE);
+                (amplitude * phase.sin()) as f32
+            })
+            .collect()
+    }
+
+    // one unsmoothed frame of `samples`
+    fn spectrum(engine: &mut FFTEngine, samples: &[f32]) -> Vec<f64> {
+        engine.set_src_buf(samples);
+        engine.apply_window();
+        engine.apply_fft();
+        engine.get_bins()
+    }
+
+    fn peak(bins: &[f64]) -> (usize, f64) {
+        bins.iter().copied().enumerate().fold(
+            (0, f64::MIN),
+            |max, (i, db)| if db > max.1 { (i, db) } else { max },
+        )
+    }
+
+    #[test]
+    fn amplitude_correction_reads_a_tone_the_same_with_every_window() {
+        let bin_step = f64::from(SAMPLE_RATE) / LEN as f64;
+        let full_scale = sine(100. * bin_step, 1.);
+        let half_bin = sine(100.5 * bin_step, 1.);
+        let expected = 20. * 0.5f64.log10();
+        for window in WindowType::ALL {
+            let mut engine = FFTEngine::new(SAMPLE_RATE, LEN / 2 + 1, 0., window.clone());
+            engine.set_correction(Correction::Amplitude);
+            let (bin, db) = peak(&spectrum(&mut engine, &full_scale));
+            assert_eq!(bin, 100, "{window}");
+            assert!((db - expected).abs() < 0.01, "{window} reads {db:.3} dB");
+
+            // between two bins only the flat-top window keeps the level
+            if window == WindowType::FlatTop {
+                let (_, db) = peak(&spectrum(&mut engine, &half_bin));
+                assert!((db - expected).abs() < 0.1, "{window} reads {db:.3} dB");
+            }
+        }
+    }
+
+    #[test]
+    fn noise_correction_reads_noise_the_same_with_every_window() {
+        // deterministic white noise from a linear congruential generator
+        let mut state = 12345u64;
+        let noise: Vec<f32> = (0..LEN)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as f32 / (1u64 << 31) as f32 - 0.5
+            })
+            .collect();
+        let mean_db = |window: WindowType| {
+            let mut engine = FFTEngine::new(SAMPLE_RATE, LEN / 2 + 1, 0., window);
+            engine.set_correction(Correction::Noise);
+            let bins = spectrum(&mut engine, &noise);
+            let power = bins[1..].iter().map(|db| 10f64.powf(db / 10.)).sum::<f64>();
+            10. * (power / (bins.len() - 1) as f64).log10()
+        };
+        let reference = mean_db(WindowType::Rectangular);
+        for window in WindowType::ALL {
+            let db = mean_db(window.clone());
+            assert!(
+                (db - reference).abs() < 0.2,
+                "{window}: {db:.2} dB, not {reference:.2} dB"
+            );
+        }
+    }
 
-    pub fn log(&mut self, s: String) {
-        self.logger.write_all(s.as_bytes()).unwrap();
+    #[test]
+    fn tones_are_corrected_by_default() {
+        let engine = FFTEngine::new(SAMPLE_RATE, LEN / 2 + 1, 0., WindowType::BlackmanHarris);
+        assert_eq!(engine.get_correction(), Correction::Amplitude);
+    }
+
+    #[test]
+    fn no_correction_leaves_the_window_attenuation() {
+        let bin_step = f64::from(SAMPLE_RATE) / LEN as f64;
+        let mut engine = FFTEngine::new(SAMPLE_RATE, LEN / 2 + 1, 0., WindowType::Hanning);
+        engine.set_correction(Correction::None);
+        let (_, db) = peak(&spectrum(&mut engine, &sine(100. * bin_step, 1.)));
+        // half the amplitude, and half of that again for the Hann window's coherent gain
+        assert!((db - 20. * 0.25f64.log10()).abs() < 0.01, "{db:.3} dB");
+    }
+
+    #[test]
+    fn zero_padding_keeps_the_peak_and_halves_the_bin_spacing() {
+        let bin_step = f64::from(SAMPLE_RATE) / LEN as f64;
+        let tone = sine(100. * bin_step, 1.);
+        let mut plain = FFTEngine::new(SAMPLE_RATE, LEN / 2 + 1, 0., WindowType::Hanning);
+        let (plain_bin, plain_db) = peak(&spectrum(&mut plain, &tone));
+
+        let mut padded = FFTEngine::new(SAMPLE_RATE, LEN + 1, 0., WindowType::Hanning);
+        padded.set_zero_pad(2);
+        let bins = spectrum(&mut padded, &tone);
+        let (padded_bin, padded_db) = peak(&bins);
+
+        assert_eq!(plain_bin, 100);
+        assert_eq!(padded_bin, 2 * plain_bin);
+        assert!(
+            (padded_db - plain_db).abs() < 1e-6,
+            "{padded_db} dB, not {plain_db} dB"
+        );
+        // the bins in between are new, those of the unpadded block are all still there,
+        // compared linearly since far from the tone they are down at the rounding noise
+        let plain_bins = spectrum(&mut plain, &tone);
+        let linear = |db: f64| 10f64.powf(db / 20.);
+        for (i, db) in plain_bins.iter().enumerate().take(LEN / 2) {
+            assert!((linear(bins[2 * i]) - linear(*db)).abs() < 1e-6, "bin {i}");
+        }
+    }
+
+    #[test]
+    fn zero_padding_moves_a_tone_between_bins_closer_to_its_frequency() {
+        let bin_step = f64::from(SAMPLE_RATE) / LEN as f64;
+        let freq = 100.3 * bin_step;
+        let tone = sine(freq, 1.);
+        let error = |factor: usize| {
+            let mut engine =
+                FFTEngine::new(SAMPLE_RATE, LEN * factor / 2 + 1, 0., WindowType::Hanning);
+            engine.set_zero_pad(factor);
+            let (bin, _) = peak(&spectrum(&mut engine, &tone));
+            (bin as f64 * bin_step / factor as f64 - freq).abs()
+        };
+        assert!(error(1) > bin_step / 4.);
+        assert!(error(8) <= bin_step / 16.);
     }
 }
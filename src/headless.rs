@@ -0,0 +1,327 @@
+use crate::{
+    app::{spectrum_bins, Args},
+    capture::{build_stream, find_device_idx, find_host, StreamOutput, XrunCount},
+    curve::{self, Curve, CurveKind},
+    error::{Error, Result},
+    wav,
+};
+use audiolyzer::fft::*;
+
+use std::{
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use cpal::traits::HostTrait;
+
+// returned when any frame violated --upper-mask or --lower-mask, 1 is used for errors
+const MASK_FAILURE: u8 = 2;
+
+struct FrameStats {
+    time: f64,
+    rms_db: f64,
+    peak_freq: f64,
+    peak_db: f64,
+    // bins outside of the masks
+    violations: usize,
+}
+
+// collects per-frame statistics and prints them either as they arrive or as a summary
+struct Report {
+    per_frame: bool,
+    has_masks: bool,
+    frames: Vec<FrameStats>,
+}
+
+impl Report {
+    fn new(per_frame: bool, has_masks: bool) -> Self {
+        if per_frame {
+            let masks = if has_masks { ",mask_violations" } else { "" };
+            println!("time_s,rms_db,peak_hz,peak_db{masks}");
+        }
+        Report {
+            per_frame,
+            has_masks,
+            frames: vec![],
+        }
+    }
+
+    fn push(&mut self, frame: FrameStats) {
+        if self.per_frame {
+            let masks = if self.has_masks {
+                format!(",{}", frame.violations)
+            } else {
+                String::new()
+            };
+            println!(
+                "{:.3},{:.1},{:.1},{:.1}{masks}",
+                frame.time, frame.rms_db, frame.peak_freq, frame.peak_db
+            );
+        }
+        self.frames.push(frame);
+    }
+
+    fn failed_frames(&self) -> usize {
+        self.frames.iter().filter(|f| f.violations > 0).count()
+    }
+
+    fn print_summary(&self) {
+        if self.per_frame {
+            return;
+        }
+
+        println!("frames: {}", self.frames.len());
+        let (Some(first), Some(last)) = (self.frames.first(), self.frames.last()) else {
+            return;
+        };
+        println!("duration: {:.3} s", last.time - first.time);
+
+        let mean_rms = self.frames.iter().map(|f| f.rms_db).sum::<f64>() / self.frames.len() as f64;
+        let max_rms = self
+            .frames
+            .iter()
+            .map(|f| f.rms_db)
+            .fold(f64::MIN, f64::max);
+        println!("rms: mean {mean_rms:.1} dB, max {max_rms:.1} dB");
+
+        let loudest = self
+            .frames
+            .iter()
+            .max_by(|a, b| a.peak_db.total_cmp(&b.peak_db))
+            .unwrap();
+        println!(
+            "peak: {:.1} Hz at {:.1} dB",
+            loudest.peak_freq, loudest.peak_db
+        );
+
+        if self.has_masks {
+            match self.failed_frames() {
+                0 => println!("mask: pass"),
+                failed => println!("mask: fail in {failed} of {} frames", self.frames.len()),
+            }
+        }
+    }
+}
+
+pub fn run(args: &Args) -> Result<ExitCode> {
+    let curves = curve::load_all(args)?;
+    let has_masks = curves.iter().any(|c| c.kind != CurveKind::Reference);
+    let mut report = Report::new(args.per_frame, has_masks);
+
+    match &args.input_file {
+        Some(path) => {
+            let wav = wav::read_mono(path)?;
+            let mut fft_engine = FFTEngine::new(
+                wav.sample_rate,
+                spectrum_bins(args),
+                args.smoothing_constant,
+                args.window.clone(),
+            );
+            fft_engine.set_transform(args.constant_q.map_or(Transform::Fft, Transform::ConstantQ));
+            fft_engine.set_zero_pad(args.zero_pad as usize);
+            fft_engine.set_correction(args.correction);
+            let max_frames = args
+                .duration
+                .map(|d| (d * f64::from(wav.sample_rate) / f64::from(args.fft_size)) as usize)
+                .unwrap_or(usize::MAX);
+
+            for (i, chunk) in wav
+                .samples
+                .chunks_exact(args.fft_size as usize)
+                .take(max_frames)
+                .enumerate()
+            {
+                let time = (i * chunk.len()) as f64 / f64::from(wav.sample_rate);
+                report.push(analyze_frame(&mut fft_engine, &curves, chunk, time));
+            }
+        }
+        None => {
+            let duration = args
+                .duration
+                .ok_or_else(|| Error::Config("headless capture requires --duration".into()))?;
+
+            let host = find_host(args.host.as_deref())?;
+            let in_devices: Vec<_> = host.input_devices()?.collect();
+            let idx =
+                find_device_idx(&host, &in_devices, args.device.as_deref()).ok_or_else(|| {
+                    Error::DeviceNotFound(args.device.clone().unwrap_or_else(|| "default".into()))
+                })?;
+
+            let data_lock = Arc::new(Mutex::new(StreamOutput::default()));
+            let (_stream, stream_info) = build_stream(
+                &in_devices[idx],
+                args.sample_rate,
+                args.channels,
+                args.fft_size,
+                data_lock.clone(),
+            )?;
+            eprintln!("capturing at {stream_info}");
+            let mut fft_engine = FFTEngine::new(
+                stream_info.sample_rate,
+                spectrum_bins(args),
+                args.smoothing_constant,
+                args.window.clone(),
+            );
+            fft_engine.set_transform(args.constant_q.map_or(Transform::Fft, Transform::ConstantQ));
+            fft_engine.set_zero_pad(args.zero_pad as usize);
+            fft_engine.set_correction(args.correction);
+
+            let tick_rate = Duration::from_millis(1000 / u64::from(args.fps));
+            let mut xruns = XrunCount::default();
+            let start = Instant::now();
+            while start.elapsed().as_secs_f64() < duration {
+                sleep(tick_rate);
+                // take the frame once it is full so that no sample is analyzed twice
+                let data = match data_lock.lock() {
+                    Ok(mut res) => {
+                        if let Some(error) = res.error.take() {
+                            return Err(Error::Stream(error));
+                        }
+                        xruns.catch_up(&res.xruns);
+                        if res.data.len() < res.frame_len {
+                            continue;
+                        }
+                        std::mem::take(&mut res.data)
+                    }
+                    _ => continue,
+                };
+                report.push(analyze_frame(
+                    &mut fft_engine,
+                    &curves,
+                    &data,
+                    start.elapsed().as_secs_f64(),
+                ));
+            }
+        }
+    }
+
+    report.print_summary();
+
+    if report.failed_frames() > 0 {
+        return Ok(ExitCode::from(MASK_FAILURE));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn analyze_frame(
+    fft_engine: &mut FFTEngine,
+    curves: &[Curve],
+    samples: &[f32],
+    time: f64,
+) -> FrameStats {
+    fft_engine.set_src_buf(samples);
+
+    let src = fft_engine.get_src_buf();
+    let rms = (src.iter().map(|s| f64::from(*s).powi(2)).sum::<f64>() / src.len() as f64).sqrt();
+
+    fft_engine.apply_window();
+    fft_engine.apply_fft();
+
+    // only the first len / 2 + 1 bins of the padded block hold output for this frame, and
+    // bin 0 is DC
+    let bins = fft_engine.get_bins();
+    let padded_len = samples.len() * fft_engine.get_zero_pad();
+    let freq_step = f64::from(fft_engine.get_sample_rate()) / padded_len as f64;
+    let spectrum = &bins[..padded_len / 2 + 1];
+    let (peak_idx, peak_db) = spectrum[1..]
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, db)| (i + 1, *db))
+        .unwrap_or((0, f64::MIN));
+
+    FrameStats {
+        time,
+        rms_db: 20. * rms.max(1e-12).log10(),
+        peak_freq: freq_step * peak_idx as f64,
+        peak_db,
+        violations: curves
+            .iter()
+            .map(|c| c.violations(spectrum, freq_step).len())
+            .sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    // a full scale tone centered on bin 20 of a 1024 sample block
+    fn tone() -> Vec<f32> {
+        let freq = 20. * f64::from(SAMPLE_RATE) / 1024.;
+        (0..1024)
+            .map(|i| {
+                (2. * std::f64::consts::PI * freq * i as f64 / f64::from(SAMPLE_RATE)).sin() as f32
+            })
+            .collect()
+    }
+
+    fn engine() -> FFTEngine {
+        FFTEngine::new(SAMPLE_RATE, 513, 0., WindowType::Hanning)
+    }
+
+    #[test]
+    fn frame_stats_of_a_tone() {
+        let stats = analyze_frame(&mut engine(), &[], &tone(), 1.5);
+        assert_eq!(stats.time, 1.5);
+        assert_eq!(stats.peak_freq, 937.5);
+        // a sine's RMS is 1/sqrt(2) of its amplitude
+        assert!((stats.rms_db + 3.01).abs() < 0.01, "{}", stats.rms_db);
+        assert_eq!(stats.violations, 0);
+    }
+
+    #[test]
+    fn only_the_spectrum_of_the_padded_block_is_checked() {
+        let mut fft_engine = FFTEngine::new(SAMPLE_RATE, 22050, 0., WindowType::Hanning);
+        fft_engine.set_zero_pad(2);
+        let stats = analyze_frame(&mut fft_engine, &[], &tone(), 0.);
+        assert_eq!(stats.peak_freq, 937.5);
+
+        // above Nyquist the engine only holds placeholder bins, which must not count
+        let mask = Curve {
+            name: "mask".into(),
+            kind: CurveKind::LowerMask,
+            points: vec![(0., 10.), (100000., 10.)],
+        };
+        let stats = analyze_frame(&mut fft_engine, &[mask], &tone(), 0.);
+        assert_eq!(stats.violations, 1025);
+    }
+
+    #[test]
+    fn mask_violations_are_counted_per_curve() {
+        let curve = |kind| Curve {
+            name: "mask".into(),
+            kind,
+            points: vec![(800., -60.), (1100., -60.)],
+        };
+        // the tone's main lobe is above the upper mask, everything else below the lower one
+        let curves = [
+            curve(CurveKind::UpperMask),
+            curve(CurveKind::LowerMask),
+            curve(CurveKind::Reference),
+        ];
+        let stats = analyze_frame(&mut engine(), &curves, &tone(), 0.);
+        // bins 18 to 23 lie within 800 to 1100 Hz
+        assert_eq!(stats.violations, 6);
+    }
+
+    #[test]
+    fn block_sizes_are_checked_when_parsing() {
+        let parse = |size: &str| {
+            Args::try_parse_from(["audiolyzer", "--headless", "--fft-size", size])
+                .map(|a| a.fft_size)
+        };
+        for size in ["0", "1", "63", "65537"] {
+            assert!(parse(size).is_err(), "{size}");
+        }
+        assert_eq!(parse("64").unwrap(), 64);
+        assert_eq!(parse("1000").unwrap(), 1000);
+        assert_eq!(parse("65536").unwrap(), 65536);
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    sync::mpsc::{channel, Receiver, RecvError, Sender},
+    sync::mpsc::{channel, Receiver, RecvError, RecvTimeoutError, Sender},
     thread,
     time::Duration,
 };
@@ -8,29 +8,128 @@ use ratatui::crossterm::event;
 
 use super::{key::Key, InputEvent};
 
+// the one channel the app waits on, fed by the terminal input thread and by the audio
+// callback, so neither source has to wait for the other
 pub struct Events {
     rx: Receiver<InputEvent>,
-    _tx: Sender<InputEvent>,
+    tx: Sender<InputEvent>,
+}
+
+// the terminal events the app reacts to, mouse drags and releases are ignored
+fn input_event(event: event::Event) -> Option<InputEvent> {
+    match event {
+        event::Event::Key(key) => Some(InputEvent::Input(Key::from(key))),
+        event::Event::Mouse(mouse) => match mouse.kind {
+            event::MouseEventKind::Moved
+            | event::MouseEventKind::Down(_)
+            | event::MouseEventKind::ScrollUp
+            | event::MouseEventKind::ScrollDown
+            | event::MouseEventKind::ScrollLeft
+            | event::MouseEventKind::ScrollRight => Some(InputEvent::Mouse(mouse)),
+            _ => None,
+        },
+        event::Event::Resize(..) => Some(InputEvent::Resize),
+        _ => None,
+    }
 }
 
 impl Events {
-    pub fn new(tick_rate: Duration) -> Self {
+    pub fn new() -> Self {
         let (tx, rx) = channel();
 
         let event_tx = tx.clone();
-        thread::spawn(move || loop {
-            if event::poll(tick_rate).unwrap() {
-                if let event::Event::Key(key) = event::read().unwrap() {
-                    let key = Key::from(key);
-                    event_tx.send(InputEvent::Input(key)).unwrap();
+        // stdin is gone once reading fails, nothing more can arrive
+        thread::spawn(move || {
+            while let Ok(event) = event::read() {
+                let Some(event) = input_event(event) else {
+                    continue;
+                };
+                // the receiver is gone once the app shuts down
+                if event_tx.send(event).is_err() {
+                    break;
                 }
             }
-            event_tx.send(InputEvent::Tick).unwrap();
         });
 
-        Events { rx, _tx: tx }
+        Events { rx, tx }
+    }
+
+    // for other sources of events, such as the audio callback
+    pub fn sender(&self) -> Sender<InputEvent> {
+        self.tx.clone()
     }
-    pub fn next(&self) -> Result<InputEvent, RecvError> {
-        self.rx.recv()
+
+    // waits at most `timeout`, `None` if nothing happened in the meantime
+    pub fn next(&self, timeout: Duration) -> Result<Option<InputEvent>, RecvError> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(RecvError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+
+    fn mouse(kind: MouseEventKind) -> event::Event {
+        event::Event::Mouse(MouseEvent {
+            kind,
+            column: 3,
+            row: 4,
+            modifiers: KeyModifiers::NONE,
+        })
+    }
+
+    #[test]
+    fn keys_mouse_and_resizes_are_passed_on() {
+        let key = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert!(matches!(
+            input_event(event::Event::Key(key)),
+            Some(InputEvent::Input(Key::Char('q')))
+        ));
+        assert!(matches!(
+            input_event(mouse(MouseEventKind::Down(MouseButton::Left))),
+            Some(InputEvent::Mouse(MouseEvent {
+                column: 3,
+                row: 4,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            input_event(mouse(MouseEventKind::ScrollUp)),
+            Some(InputEvent::Mouse(_))
+        ));
+        assert!(matches!(
+            input_event(event::Event::Resize(80, 24)),
+            Some(InputEvent::Resize)
+        ));
+    }
+
+    #[test]
+    fn drags_releases_and_focus_changes_are_ignored() {
+        assert!(input_event(mouse(MouseEventKind::Drag(MouseButton::Left))).is_none());
+        assert!(input_event(mouse(MouseEventKind::Up(MouseButton::Left))).is_none());
+        assert!(input_event(event::Event::FocusGained).is_none());
+    }
+
+    #[test]
+    fn other_sources_share_the_channel() {
+        // without the terminal thread, which would read from the test's terminal
+        let (tx, rx) = channel();
+        let events = Events { rx, tx };
+        assert!(matches!(events.next(Duration::from_millis(1)), Ok(None)));
+
+        let sender = events.sender();
+        thread::spawn(move || sender.send(InputEvent::AudioFrame).unwrap())
+            .join()
+            .unwrap();
+        assert!(matches!(
+            events.next(Duration::from_secs(1)),
+            Ok(Some(InputEvent::AudioFrame))
+        ));
     }
 }
@@ -1,4 +1,7 @@
-use std::fmt::{self, Display, Formatter};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
 
 use ratatui::crossterm::event;
 
@@ -69,41 +72,6 @@ pub enum Key {
 }
 
 impl Key {
-    /// If exit
-    pub fn is_exit(&self) -> bool {
-        matches!(self, Key::Ctrl('c') | Key::Char('q') | Key::Esc)
-    }
-
-    ///If right arrow
-    pub fn is_right_arrow(&self) -> bool {
-        matches!(self, Key::Right)
-    }
-
-    ///If left arrow
-    pub fn is_left_arrow(&self) -> bool {
-        matches!(self, Key::Left)
-    }
-
-    ///If up arrow
-    pub fn is_up_arrow(&self) -> bool {
-        matches!(self, Key::Up)
-    }
-
-    ///If down arrow
-    pub fn is_down_arrow(&self) -> bool {
-        matches!(self, Key::Down)
-    }
-
-    ///If down arrow
-    pub fn is_unknown(&self) -> bool {
-        matches!(self, Key::Unknown)
-    }
-
-    ///If down arrow
-    pub fn is_enter(&self) -> bool {
-        matches!(self, Key::Enter)
-    }
-
     /// Returns the function key corresponding to the given number
     ///
     /// 1 -> F1, etc...
@@ -145,6 +113,70 @@ impl Display for Key {
     }
 }
 
+/// Parses the names used by `Display`, with or without the angle brackets and ignoring case,
+/// e.g. `q`, `<Ctrl+c>`, `alt+x`, `ctrl-x`, `Space`, `F1` or `PageDown`.
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // only a pair of brackets around a name, `<` on its own is a key as well
+        let name = s
+            .strip_prefix('<')
+            .and_then(|name| name.strip_suffix('>'))
+            .filter(|name| !name.is_empty())
+            .unwrap_or(s);
+
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Key::Char(c));
+        }
+
+        let lower = name.to_ascii_lowercase();
+        let modified = |prefix: &str| -> Option<char> {
+            let rest = lower.strip_prefix(prefix)?;
+            match rest {
+                "space" => Some(' '),
+                _ => {
+                    let mut chars = rest.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => Some(c),
+                        _ => None,
+                    }
+                }
+            }
+        };
+        if let Some(c) = modified("ctrl+").or_else(|| modified("ctrl-")) {
+            return Ok(Key::Ctrl(c));
+        }
+        if let Some(c) = modified("alt+").or_else(|| modified("alt-")) {
+            return Ok(Key::Alt(c));
+        }
+
+        let key = match lower.as_str() {
+            "enter" | "return" => Key::Enter,
+            "tab" => Key::Tab,
+            "backspace" => Key::Backspace,
+            "esc" | "escape" => Key::Esc,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "ins" | "insert" => Key::Ins,
+            "delete" | "del" => Key::Delete,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            "space" => Key::Char(' '),
+            _ => match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n) if n <= 12 => Key::from_f(n),
+                _ => return Err(format!("unknown key: {s}")),
+            },
+        };
+        Ok(key)
+    }
+}
+
 impl From<event::KeyEvent> for Key {
     fn from(key_event: event::KeyEvent) -> Self {
         match key_event {
@@ -199,7 +231,7 @@ impl From<event::KeyEvent> for Key {
             event::KeyEvent {
                 code: event::KeyCode::F(n),
                 ..
-            } => Key::from_f(n),
+            } if n <= 12 => Key::from_f(n),
             event::KeyEvent {
                 code: event::KeyCode::Enter,
                 ..
@@ -230,3 +262,94 @@ impl From<event::KeyEvent> for Key {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keys() {
+        let cases = [
+            ("q", Key::Char('q')),
+            ("Q", Key::Char('Q')),
+            ("<q>", Key::Char('q')),
+            ("?", Key::Char('?')),
+            ("<", Key::Char('<')),
+            (">", Key::Char('>')),
+            ("<<>", Key::Char('<')),
+            ("space", Key::Char(' ')),
+            ("<Space>", Key::Char(' ')),
+            ("ctrl+c", Key::Ctrl('c')),
+            ("<Ctrl+c>", Key::Ctrl('c')),
+            ("ctrl-x", Key::Ctrl('x')),
+            ("CTRL+Space", Key::Ctrl(' ')),
+            ("alt+x", Key::Alt('x')),
+            ("alt-x", Key::Alt('x')),
+            ("enter", Key::Enter),
+            ("Return", Key::Enter),
+            ("esc", Key::Esc),
+            ("Escape", Key::Esc),
+            ("<PageDown>", Key::PageDown),
+            ("del", Key::Delete),
+            ("insert", Key::Ins),
+            ("F1", Key::F1),
+            ("f12", Key::F12),
+        ];
+        for (s, key) in cases {
+            assert_eq!(s.parse::<Key>(), Ok(key), "{s}");
+        }
+    }
+
+    #[test]
+    fn parse_unknown_keys() {
+        for s in [
+            "",
+            "<>",
+            "hyper",
+            "ctrl+",
+            "ctrl+ab",
+            "alt+enter",
+            "f13",
+            "page down",
+        ] {
+            assert_eq!(s.parse::<Key>(), Err(format!("unknown key: {s}")), "{s}");
+        }
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        let keys = [
+            Key::Char('q'),
+            Key::Char('W'),
+            Key::Char(' '),
+            Key::Char('<'),
+            Key::Char('>'),
+            Key::Char('+'),
+            Key::Ctrl('c'),
+            Key::Ctrl('+'),
+            Key::Ctrl(' '),
+            Key::Alt('x'),
+            Key::Alt(' '),
+            Key::Enter,
+            Key::Tab,
+            Key::Backspace,
+            Key::Esc,
+            Key::Left,
+            Key::Right,
+            Key::Up,
+            Key::Down,
+            Key::Ins,
+            Key::Delete,
+            Key::Home,
+            Key::End,
+            Key::PageUp,
+            Key::PageDown,
+            Key::F0,
+            Key::F5,
+            Key::F12,
+        ];
+        for key in keys {
+            assert_eq!(key.to_string().parse::<Key>(), Ok(key), "{key}");
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use crate::{
+    config::Config,
+    error::{Error, Result},
+};
+
+use toml_edit::Value;
+
+use super::key::Key;
+
+// everything a key can be bound to, the picker and settings actions only do something while
+// the picker or the settings panel is open
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Action {
+    NextWindow,
+    PreviousWindow,
+    ToggleDevicePicker,
+    SwitchPickerFocus,
+    Next,
+    Previous,
+    PageDown,
+    PageUp,
+    PickerSelect,
+    ToggleSettings,
+    Increase,
+    Decrease,
+    FftSizeUp,
+    FftSizeDown,
+    SmoothingUp,
+    SmoothingDown,
+    NextDisplayMode,
+    NextMarker,
+    NextPane,
+    NextLayout,
+    ZoomIn,
+    ZoomOut,
+    PanLeft,
+    PanRight,
+    ResetView,
+    Freeze,
+    StoreSnapshot,
+    ClearSnapshots,
+    NextReference,
+    ToggleDifference,
+    NextTheme,
+    Trigger,
+    ToggleRecording,
+    SaveConfig,
+    TogglePerf,
+    Help,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 37] = [
+        Action::NextWindow,
+        Action::PreviousWindow,
+        Action::ToggleDevicePicker,
+        Action::SwitchPickerFocus,
+        Action::Next,
+        Action::Previous,
+        Action::PageDown,
+        Action::PageUp,
+        Action::PickerSelect,
+        Action::ToggleSettings,
+        Action::Increase,
+        Action::Decrease,
+        Action::FftSizeUp,
+        Action::FftSizeDown,
+        Action::SmoothingUp,
+        Action::SmoothingDown,
+        Action::NextDisplayMode,
+        Action::NextMarker,
+        Action::NextPane,
+        Action::NextLayout,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::ResetView,
+        Action::Freeze,
+        Action::StoreSnapshot,
+        Action::ClearSnapshots,
+        Action::NextReference,
+        Action::ToggleDifference,
+        Action::NextTheme,
+        Action::Trigger,
+        Action::ToggleRecording,
+        Action::SaveConfig,
+        Action::TogglePerf,
+        Action::Help,
+        Action::Quit,
+    ];
+
+    // the name used in the [keys] table of the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::NextWindow => "next_window",
+            Action::PreviousWindow => "previous_window",
+            Action::ToggleDevicePicker => "device_picker",
+            Action::SwitchPickerFocus => "picker_focus",
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::PickerSelect => "picker_select",
+            Action::ToggleSettings => "settings",
+            Action::Increase => "increase",
+            Action::Decrease => "decrease",
+            Action::FftSizeUp => "fft_size_up",
+            Action::FftSizeDown => "fft_size_down",
+            Action::SmoothingUp => "smoothing_up",
+            Action::SmoothingDown => "smoothing_down",
+            Action::NextDisplayMode => "display_mode",
+            Action::NextMarker => "marker",
+            Action::NextPane => "next_pane",
+            Action::NextLayout => "next_layout",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::PanLeft => "pan_left",
+            Action::PanRight => "pan_right",
+            Action::ResetView => "reset_view",
+            Action::Freeze => "freeze",
+            Action::StoreSnapshot => "snapshot",
+            Action::ClearSnapshots => "clear_snapshots",
+            Action::NextReference => "next_reference",
+            Action::ToggleDifference => "difference",
+            Action::NextTheme => "next_theme",
+            Action::Trigger => "trigger",
+            Action::ToggleRecording => "record",
+            Action::SaveConfig => "save_config",
+            Action::TogglePerf => "perf",
+            Action::Help => "help",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::NextWindow => "Cycle the window function",
+            Action::PreviousWindow => "Cycle the window function backwards",
+            Action::ToggleDevicePicker => "Open or close the device picker",
+            Action::SwitchPickerFocus => "Switch between hosts and devices in the picker",
+            Action::Next => "Move to the next entry in the picker or settings",
+            Action::Previous => "Move to the previous entry in the picker or settings",
+            Action::PageDown => "Move a page down in the device list",
+            Action::PageUp => "Move a page up in the device list",
+            Action::PickerSelect => "Use the selected host or device",
+            Action::ToggleSettings => "Open or close the settings panel",
+            Action::Increase => "Increase the selected setting",
+            Action::Decrease => "Decrease the selected setting",
+            Action::FftSizeUp => "Double the FFT size",
+            Action::FftSizeDown => "Halve the FFT size",
+            Action::SmoothingUp => "Smooth the spectrum more",
+            Action::SmoothingDown => "Smooth the spectrum less",
+            Action::NextDisplayMode => "Cycle the display mode of the focused pane",
+            Action::NextMarker => "Cycle the characters the focused pane is drawn with",
+            Action::NextPane => "Move the focus to the next pane",
+            Action::NextLayout => "Cycle the layout preset",
+            Action::ZoomIn => "Zoom in around the mouse cursor",
+            Action::ZoomOut => "Zoom out around the mouse cursor",
+            Action::PanLeft => "Pan towards lower frequencies",
+            Action::PanRight => "Pan towards higher frequencies",
+            Action::ResetView => "Reset zoom and pan",
+            Action::Freeze => "Freeze or unfreeze the spectrum",
+            Action::StoreSnapshot => "Store the spectrum as a snapshot",
+            Action::ClearSnapshots => "Clear all snapshots",
+            Action::NextReference => "Pick the next snapshot as reference",
+            Action::ToggleDifference => "Show live minus reference in dB",
+            Action::NextTheme => "Cycle the color theme",
+            Action::Trigger => "Save the pre-trigger buffer",
+            Action::ToggleRecording => "Start or stop recording",
+            Action::SaveConfig => "Save the current settings to the config file",
+            Action::TogglePerf => "Show or hide the performance overlay",
+            Action::Help => "Show or hide this help",
+            Action::Quit => "Quit",
+        }
+    }
+
+    fn default_keys(&self) -> Vec<Key> {
+        match self {
+            Action::NextWindow => vec![Key::Right, Key::Char('w')],
+            Action::PreviousWindow => vec![Key::Char('W')],
+            Action::ToggleDevicePicker => vec![Key::Left],
+            Action::SwitchPickerFocus => vec![Key::Tab],
+            Action::Next => vec![Key::Down],
+            Action::Previous => vec![Key::Up],
+            Action::PageDown => vec![Key::PageDown],
+            Action::PageUp => vec![Key::PageUp],
+            Action::PickerSelect => vec![Key::Enter],
+            Action::ToggleSettings => vec![Key::Char('o')],
+            Action::Increase => vec![Key::Char('+'), Key::Char('=')],
+            Action::Decrease => vec![Key::Char('-')],
+            Action::FftSizeUp => vec![Key::Char(']')],
+            Action::FftSizeDown => vec![Key::Char('[')],
+            Action::SmoothingUp => vec![Key::Char('.')],
+            Action::SmoothingDown => vec![Key::Char(',')],
+            Action::NextDisplayMode => vec![Key::Char('d')],
+            Action::NextMarker => vec![Key::Char('m')],
+            Action::NextPane => vec![Key::Char('p')],
+            Action::NextLayout => vec![Key::Char('L')],
+            Action::ZoomIn => vec![Key::Char('z')],
+            Action::ZoomOut => vec![Key::Char('x')],
+            Action::PanLeft => vec![Key::Char('h')],
+            Action::PanRight => vec![Key::Char('l')],
+            Action::ResetView => vec![Key::Char('0')],
+            Action::Freeze => vec![Key::Char('f')],
+            Action::StoreSnapshot => vec![Key::Char('a')],
+            Action::ClearSnapshots => vec![Key::Char('c')],
+            Action::NextReference => vec![Key::Char('b')],
+            Action::ToggleDifference => vec![Key::Char('v')],
+            Action::NextTheme => vec![Key::Char('T')],
+            Action::Trigger => vec![Key::Char('t')],
+            Action::ToggleRecording => vec![Key::Char('r')],
+            Action::SaveConfig => vec![Key::Char('s')],
+            Action::TogglePerf => vec![Key::Char('P')],
+            Action::Help => vec![Key::Char('?'), Key::F1],
+            Action::Quit => vec![Key::Char('q'), Key::Esc, Key::Ctrl('c')],
+        }
+    }
+}
+
+// which keys trigger which action, in the order of `Action::ALL` so the help lists them
+// the same way every time
+pub struct KeyMap {
+    bindings: Vec<(Action, Vec<Key>)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap {
+            bindings: Action::ALL
+                .iter()
+                .map(|action| (*action, action.default_keys()))
+                .collect(),
+        }
+    }
+}
+
+impl KeyMap {
+    // the defaults with the [keys] table of the config applied, each entry maps an action to a
+    // key or a list of keys that replace its default ones
+    pub fn new(config: &Config) -> Result<KeyMap> {
+        let mut keymap = KeyMap::default();
+
+        for (name, item) in config.section("keys") {
+            let action = Action::ALL
+                .into_iter()
+                .find(|action| action.name() == name)
+                .ok_or_else(|| Error::Config(format!("unknown action in [keys]: {name}")))?;
+            let invalid = || Error::Config(format!("keys.{name} must be a key or a list of keys"));
+            // an empty list unbinds the action
+            let names: Vec<&str> = match item.as_value() {
+                Some(Value::String(s)) => vec![s.value()],
+                Some(Value::Array(array)) => array
+                    .iter()
+                    .map(|v| v.as_str().ok_or_else(invalid))
+                    .collect::<Result<_>>()?,
+                _ => return Err(invalid()),
+            };
+            let keys = names
+                .into_iter()
+                .map(str::parse)
+                .collect::<std::result::Result<Vec<Key>, _>>()
+                .map_err(Error::Config)?;
+            keymap.bind(action, keys);
+        }
+
+        Ok(keymap)
+    }
+
+    // a key can only do one thing, so it is taken away from whatever it was bound to before
+    fn bind(&mut self, action: Action, keys: Vec<Key>) {
+        for (_, bound) in self.bindings.iter_mut() {
+            bound.retain(|key| !keys.contains(key));
+        }
+        if let Some((_, bound)) = self.bindings.iter_mut().find(|(a, _)| *a == action) {
+            *bound = keys;
+        }
+    }
+
+    pub fn action(&self, key: &Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(key))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn bindings(&self) -> &[(Action, Vec<Key>)] {
+        &self.bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn keymap(toml: &str) -> Result<KeyMap> {
+        KeyMap::new(&Config::parse(PathBuf::from("config.toml"), None, toml)?)
+    }
+
+    fn error(toml: &str) -> String {
+        match keymap(toml) {
+            Ok(_) => panic!("{toml:?} was accepted"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn every_action_has_its_default_keys() {
+        let keymap = KeyMap::default();
+        for action in Action::ALL {
+            for key in action.default_keys() {
+                assert_eq!(keymap.action(&key), Some(action), "{key}");
+            }
+        }
+        assert_eq!(keymap.action(&Key::Char('~')), None);
+    }
+
+    #[test]
+    fn bindings() {
+        // (config, key, action it should trigger)
+        let cases = [
+            ("", Key::Char('q'), Some(Action::Quit)),
+            ("[keys]\nquit = \"x\"", Key::Char('x'), Some(Action::Quit)),
+            ("[keys]\nquit = \"x\"", Key::Char('q'), None),
+            ("[keys]\nquit = \"x\"", Key::Esc, None),
+            (
+                "[keys]\nquit = [\"x\", \"ctrl-q\"]",
+                Key::Ctrl('q'),
+                Some(Action::Quit),
+            ),
+            ("[keys]\nquit = []", Key::Char('q'), None),
+            ("[keys]\nhelp = \"<F2>\"", Key::F2, Some(Action::Help)),
+            // a key bound again is taken away from its default action
+            (
+                "[keys]\nnext_window = \"q\"",
+                Key::Char('q'),
+                Some(Action::NextWindow),
+            ),
+            ("[keys]\nnext_window = \"q\"", Key::Esc, Some(Action::Quit)),
+            ("[keys]\nnext_window = \"q\"", Key::Right, None),
+            // the same key in two entries ends up with the later one
+            (
+                "[keys]\nfreeze = \"g\"\nsnapshot = \"g\"",
+                Key::Char('g'),
+                Some(Action::StoreSnapshot),
+            ),
+            (
+                "[keys]\nfreeze = \"g\"\nsnapshot = \"g\"",
+                Key::Char('f'),
+                None,
+            ),
+        ];
+        for (toml, key, action) in cases {
+            let keymap = keymap(toml).unwrap_or_else(|err| panic!("{toml:?}: {err}"));
+            assert_eq!(keymap.action(&key), action, "{key} with {toml:?}");
+        }
+    }
+
+    #[test]
+    fn bindings_of_the_profile_come_last() {
+        let toml = "[keys]\nquit = \"x\"\n[profiles.p.keys]\nquit = \"y\"";
+        let config = Config::parse(PathBuf::from("config.toml"), Some("p"), toml).unwrap();
+        let keymap = KeyMap::new(&config).unwrap();
+        assert_eq!(keymap.action(&Key::Char('y')), Some(Action::Quit));
+        assert_eq!(keymap.action(&Key::Char('x')), None);
+    }
+
+    #[test]
+    fn malformed_bindings() {
+        let cases = [
+            (
+                "[keys]\nexplode = \"x\"",
+                "unknown action in [keys]: explode",
+            ),
+            (
+                "[keys]\nquit = 3",
+                "keys.quit must be a key or a list of keys",
+            ),
+            (
+                "[keys]\nquit = [\"x\", 3]",
+                "keys.quit must be a key or a list of keys",
+            ),
+            ("[keys]\nquit = \"hyper\"", "unknown key: hyper"),
+            ("[keys]\nquit = [\"x\", \"ctrl+\"]", "unknown key: ctrl+"),
+        ];
+        for (toml, message) in cases {
+            assert_eq!(
+                error(toml),
+                format!("invalid configuration: {message}"),
+                "{toml:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn action_names_are_unique() {
+        for (i, action) in Action::ALL.iter().enumerate() {
+            assert!(
+                Action::ALL[i + 1..]
+                    .iter()
+                    .all(|other| other.name() != action.name()),
+                "{}",
+                action.name()
+            );
+        }
+    }
+}
@@ -1,9 +1,16 @@
 pub mod events;
 pub mod key;
+pub mod keymap;
 
 use key::Key;
+use ratatui::crossterm::event::MouseEvent;
 
 pub enum InputEvent {
     Input(Key),
-    Tick,
+    // only mouse movement, clicks and the wheel
+    Mouse(MouseEvent),
+    // the terminal changed size and has to be redrawn right away
+    Resize,
+    // the audio callback delivered new samples since the app last took them
+    AudioFrame,
 }
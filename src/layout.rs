@@ -0,0 +1,263 @@
+use crate::{
+    config::Config,
+    error::{Error, Result},
+};
+use audiolyzer::plot::Marker;
+
+use std::str::FromStr;
+
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+
+// what a pane shows
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum View {
+    Spectrum,
+    // the spectrum over time, newest at the top
+    Waterfall,
+    // RMS and peak level of the latest block
+    Meters,
+    // the waveform of the latest block
+    Scope,
+}
+
+impl View {
+    const ALL: [View; 4] = [View::Spectrum, View::Waterfall, View::Meters, View::Scope];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            View::Spectrum => "spectrum",
+            View::Waterfall => "waterfall",
+            View::Meters => "meters",
+            View::Scope => "scope",
+        }
+    }
+
+    // whether the pane draws through a display strategy and marker
+    pub fn has_display_mode(&self) -> bool {
+        matches!(self, View::Spectrum | View::Scope)
+    }
+
+    // whether the pane has a frequency axis, which follows zooming and panning
+    pub fn has_freq_axis(&self) -> bool {
+        matches!(self, View::Spectrum | View::Waterfall)
+    }
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        View::ALL
+            .into_iter()
+            .find(|view| view.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown view: {s}"))
+    }
+}
+
+// a named arrangement of panes: columns side by side, each holding panes stacked on top of
+// each other, every column and pane with its share of the space
+pub struct Preset {
+    pub name: String,
+    columns: Vec<(u16, Vec<(u16, View)>)>,
+}
+
+impl Preset {
+    fn builtin() -> Vec<Preset> {
+        [
+            ("single", "spectrum"),
+            ("stacked", "spectrum*3/waterfall*2"),
+            ("scope", "spectrum*2/scope"),
+            ("dashboard", "4:spectrum*3/waterfall*2|1:meters/scope"),
+        ]
+        .into_iter()
+        .map(|(name, spec)| Preset::parse(name, spec).expect("built-in presets are valid"))
+        .collect()
+    }
+
+    // `|` separates columns and `/` the panes within a column, a column may start with `N:`
+    // for its share of the width and a pane may end with `*N` for its share of the height,
+    // e.g. "3:spectrum*2/waterfall|1:meters"
+    pub fn parse(name: &str, spec: &str) -> std::result::Result<Preset, String> {
+        let weight = |weight: &str| {
+            weight
+                .trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|w| *w > 0)
+                .ok_or_else(|| format!("invalid weight: {weight}"))
+        };
+        let columns = spec
+            .split('|')
+            .map(|column| {
+                let (width, panes) = match column.split_once(':') {
+                    Some((width, panes)) => (weight(width)?, panes),
+                    None => (1, column),
+                };
+                let panes = panes
+                    .split('/')
+                    .map(|pane| {
+                        let (view, height) = match pane.split_once('*') {
+                            Some((view, height)) => (view, weight(height)?),
+                            None => (pane, 1),
+                        };
+                        Ok((height, view.trim().parse()?))
+                    })
+                    .collect::<std::result::Result<_, String>>()?;
+                Ok((width, panes))
+            })
+            .collect::<std::result::Result<_, String>>()?;
+        Ok(Preset {
+            name: name.into(),
+            columns,
+        })
+    }
+
+    // column by column, top to bottom
+    pub fn views(&self) -> Vec<View> {
+        self.columns
+            .iter()
+            .flat_map(|(_, panes)| panes.iter().map(|(_, view)| *view))
+            .collect()
+    }
+
+    // the area of every pane, in the order of `views`
+    pub fn areas(&self, area: Rect) -> Vec<Rect> {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(ratios(self.columns.iter().map(|(width, _)| *width)))
+            .split(area);
+        self.columns
+            .iter()
+            .zip(columns.iter())
+            .flat_map(|((_, panes), column)| {
+                Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints(ratios(panes.iter().map(|(height, _)| *height)))
+                    .split(*column)
+                    .to_vec()
+            })
+            .collect()
+    }
+}
+
+fn ratios(weights: impl Iterator<Item = u16> + Clone) -> Vec<Constraint> {
+    let total: u32 = weights.clone().map(u32::from).sum();
+    weights
+        .map(|weight| Constraint::Ratio(u32::from(weight), total))
+        .collect()
+}
+
+// how one pane of the current preset draws, changed by the keys while it has focus
+pub struct Pane {
+    pub view: View,
+    pub display_mode: String,
+    pub marker: Marker,
+}
+
+// the built-in presets followed by the [layouts] table of the config, where every entry maps
+// a name to a layout in the format of `Preset::parse`
+pub fn load(config: &Config) -> Result<Vec<Preset>> {
+    let mut presets = Preset::builtin();
+
+    for (name, item) in config.section("layouts") {
+        let spec = item
+            .as_str()
+            .ok_or_else(|| Error::Config(format!("layouts.{name} must be a string")))?;
+        let preset = Preset::parse(name, spec)
+            .map_err(|message| Error::Config(format!("layouts.{name}: {message}")))?;
+        match presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(existing) => *existing = preset,
+            None => presets.push(preset),
+        }
+    }
+
+    Ok(presets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    #[test]
+    fn parse_presets() {
+        let cases = [
+            ("spectrum", vec![(1, vec![(1, View::Spectrum)])]),
+            (
+                "spectrum*3/waterfall*2",
+                vec![(1, vec![(3, View::Spectrum), (2, View::Waterfall)])],
+            ),
+            (
+                " 4:Spectrum / waterfall | 1 : meters*2/scope ",
+                vec![
+                    (4, vec![(1, View::Spectrum), (1, View::Waterfall)]),
+                    (1, vec![(2, View::Meters), (1, View::Scope)]),
+                ],
+            ),
+        ];
+        for (spec, columns) in cases {
+            let preset = Preset::parse("test", spec).unwrap_or_else(|err| panic!("{spec}: {err}"));
+            assert_eq!(preset.columns, columns, "{spec}");
+        }
+    }
+
+    #[test]
+    fn parse_malformed_presets() {
+        let cases = [
+            ("", "unknown view: "),
+            ("spectrum|", "unknown view: "),
+            ("spectrum//scope", "unknown view: "),
+            ("spectrogram", "unknown view: spectrogram"),
+            ("0:spectrum", "invalid weight: 0"),
+            ("spectrum*x", "invalid weight: x"),
+            ("spectrum*-1", "invalid weight: -1"),
+        ];
+        for (spec, error) in cases {
+            assert_eq!(
+                Preset::parse("test", spec).err().as_deref(),
+                Some(error),
+                "{spec}"
+            );
+        }
+    }
+
+    #[test]
+    fn views_and_areas_follow_the_weights() {
+        let preset = Preset::parse("test", "3:spectrum*3/waterfall|1:meters").unwrap();
+        assert_eq!(
+            preset.views(),
+            [View::Spectrum, View::Waterfall, View::Meters]
+        );
+        let areas = preset.areas(Rect::new(0, 0, 100, 40));
+        assert_eq!(areas[0], Rect::new(0, 0, 75, 30));
+        assert_eq!(areas[1], Rect::new(0, 30, 75, 10));
+        assert_eq!(areas[2], Rect::new(75, 0, 25, 40));
+    }
+
+    #[test]
+    fn config_layouts_add_to_and_replace_the_builtin_ones() {
+        let toml = "[layouts]\nsingle = \"scope\"\nwide = \"spectrum|spectrum\"";
+        let config = Config::parse(PathBuf::from("config.toml"), None, toml).unwrap();
+        let presets = load(&config).unwrap();
+        let views = |name: &str| presets.iter().find(|p| p.name == name).unwrap().views();
+        assert_eq!(views("single"), [View::Scope]);
+        assert_eq!(views("wide"), [View::Spectrum, View::Spectrum]);
+        assert_eq!(presets.len(), Preset::builtin().len() + 1);
+    }
+
+    #[test]
+    fn malformed_config_layouts() {
+        let cases = [
+            ("[layouts]\nx = 1", "layouts.x must be a string"),
+            ("[layouts]\nx = \"foo\"", "layouts.x: unknown view: foo"),
+        ];
+        for (toml, message) in cases {
+            let config = Config::parse(PathBuf::from("config.toml"), None, toml).unwrap();
+            let Err(err) = load(&config) else {
+                panic!("{toml:?} was accepted");
+            };
+            assert_eq!(err.to_string(), format!("invalid configuration: {message}"));
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Spectrum analysis behind the audiolyzer visualizer.
+//!
+//! The analysis core ([`fft`], [`window`], [`constant_q`] and [`bands`]) only depends on the FFT and window crates and the
+//! `log` facade, so it can be used without any audio or terminal dependencies. The ratatui
+//! [`display`] strategies and the [`plot`] widget they draw onto are available with the `tui`
+//! feature.
+//!
+//! ```
+//! use audiolyzer::fft::{FFTEngine, WindowType};
+//!
+//! let samples: Vec<f32> = (0..1024)
+//!     .map(|i| (2. * std::f32::consts::PI * 1000. * i as f32 / 44100.).sin())
+//!     .collect();
+//!
+//! let mut engine = FFTEngine::new(44100, 513, 0., WindowType::Blackman);
+//! engine.set_src_buf(&samples);
+//! engine.apply_window();
+//! engine.apply_fft();
+//!
+//! let freq_step = 44100. / 1024.;
+//! let bands = audiolyzer::bands::octave_bands(&engine.get_bins(), freq_step, 3, 20., 20000.);
+//! let loudest = bands
+//!     .iter()
+//!     .max_by(|a, b| a.level_db.total_cmp(&b.level_db))
+//!     .unwrap();
+//! assert_eq!(loudest.center, 1000.);
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod bands;
+pub mod constant_q;
+#[cfg(feature = "tui")]
+pub mod display;
+pub mod fft;
+#[cfg(feature = "tui")]
+pub mod plot;
+pub mod window;
@@ -0,0 +1,165 @@
+use crate::error::{Error, Result};
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{LineWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+// once the log grows past this it is moved to <file>.1, replacing an older one
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+
+// appends records to --log-file, nothing is logged without one since stdout and stderr
+// belong to the terminal UI
+struct FileLogger {
+    path: PathBuf,
+    level: LevelFilter,
+    start: Instant,
+    file: Mutex<(LineWriter<File>, u64)>,
+}
+
+impl FileLogger {
+    fn open(path: &Path) -> Result<(LineWriter<File>, u64)> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok((LineWriter::new(file), len))
+    }
+
+    fn rotate(&self, file: &mut (LineWriter<File>, u64)) -> Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+        *file = FileLogger::open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{:>10.3} {:<5} {}: {}\n",
+            self.start.elapsed().as_secs_f64(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        // a failed write can't be reported anywhere, the record is dropped
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        if file.1 > MAX_LOG_BYTES && self.rotate(&mut file).is_err() {
+            return;
+        }
+        if file.0.write_all(line.as_bytes()).is_ok() {
+            file.1 += line.len() as u64;
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.0.flush();
+        }
+    }
+}
+
+pub fn init(path: Option<&Path>, level: LevelFilter) -> Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    let logger = FileLogger {
+        path: path.to_path_buf(),
+        level,
+        start: Instant::now(),
+        file: Mutex::new(
+            FileLogger::open(path)
+                .map_err(|err| Error::Config(format!("--log-file {}: {err}", path.display())))?,
+        ),
+    };
+    // only fails when a logger is already installed, which init is never called twice for
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use log::Level;
+
+    fn logger(name: &str, level: LevelFilter, len: u64) -> FileLogger {
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        let (file, _) = FileLogger::open(&path).unwrap();
+        FileLogger {
+            path,
+            level,
+            start: Instant::now(),
+            file: Mutex::new((file, len)),
+        }
+    }
+
+    fn log(logger: &FileLogger, level: Level, message: &str) {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .target("audiolyzer::capture")
+                .args(format_args!("{message}"))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn records_below_the_level_are_left_out() {
+        let logger = logger("audiolyzer-log-level.log", LevelFilter::Info, 0);
+        log(&logger, Level::Info, "switching to device USB Mic");
+        log(&logger, Level::Debug, "14.8 fps");
+        log(&logger, Level::Warn, "1 xrun(s), 480 samples of input lost");
+
+        let contents = fs::read_to_string(&logger.path).unwrap();
+        fs::remove_file(&logger.path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2, "{contents}");
+        // seconds since the start, right aligned
+        assert!(lines[0].starts_with("     0.0"), "{}", lines[0]);
+        assert!(lines[0].ends_with(" INFO  audiolyzer::capture: switching to device USB Mic"));
+        assert!(
+            lines[1].ends_with(" WARN  audiolyzer::capture: 1 xrun(s), 480 samples of input lost")
+        );
+        assert_eq!(logger.file.lock().unwrap().1, contents.len() as u64);
+    }
+
+    #[test]
+    fn full_logs_are_moved_aside() {
+        let logger = logger(
+            "audiolyzer-log-rotate.log",
+            LevelFilter::Trace,
+            MAX_LOG_BYTES + 1,
+        );
+        let mut rotated = logger.path.clone().into_os_string();
+        rotated.push(".1");
+        log(&logger, Level::Info, "first");
+        log(&logger, Level::Info, "second");
+
+        let old = fs::read_to_string(&rotated).unwrap();
+        let new = fs::read_to_string(&logger.path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+        fs::remove_file(&logger.path).unwrap();
+        // the old file was empty here, only its length was faked
+        assert_eq!(old, "");
+        assert_eq!(new.lines().count(), 2);
+        assert!(new.contains("first") && new.contains("second"));
+    }
+}
@@ -1,131 +1,388 @@
+mod analyze;
 mod app;
-mod display;
-mod fft;
+mod capture;
+mod config;
+mod curve;
+mod error;
+mod headless;
 mod inputs;
+mod layout;
+mod logging;
+mod perf;
+mod pretrigger;
+mod recorder;
+mod settings;
+mod snapshot;
+mod spectrogram;
+mod theme;
 mod ui;
+mod wav;
 
 use crate::{
-    app::App,
-    fft::*,
-    inputs::{events::Events, key::Key, InputEvent},
+    app::{frame_interval, App, Command},
+    error::Result,
+    inputs::{events::Events, key::Key, keymap::Action, InputEvent},
+    settings::Setting,
 };
 
 use std::{
-    io,
+    io::{self, Stdout},
+    panic,
+    process::ExitCode,
     time::{Duration, Instant},
 };
 
-use cpal::traits::HostTrait;
-
 use ratatui::{
     backend::CrosstermBackend,
     crossterm::{
-        event::{DisableMouseCapture, EnableMouseCapture},
+        cursor::Show,
+        event::{
+            DisableMouseCapture, EnableMouseCapture, KeyModifiers, MouseEvent, MouseEventKind,
+        },
         execute,
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     },
+    layout::{Position, Rect},
     prelude::Backend,
     Terminal,
 };
 use ui::ui;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    enable_raw_mode().unwrap();
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture).unwrap();
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend).unwrap();
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("audiolyzer: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    let mut app = App::new();
+fn run() -> Result<ExitCode> {
+    let (args, config) = config::parse_args()?;
+    logging::init(args.log_file.as_deref(), args.log_level)?;
+    log::info!("audiolyzer {} starting", env!("CARGO_PKG_VERSION"));
+    log::debug!("config file {}", config.path.display());
+    for notice in &config.notices {
+        log::warn!("{notice}");
+        eprintln!("audiolyzer: {notice}");
+    }
+    if let Some(Command::Analyze(analyze_args)) = &args.command {
+        analyze::run(&args, analyze_args)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.headless {
+        return headless::run(&args);
+    }
+
+    let mut app = App::new(args, config)?;
+
+    // a panic skips the restore code below, so the hook puts the terminal back before the
+    // panic message is printed
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        default_hook(info);
+    }));
+
+    let mut terminal = setup_terminal()?;
     let res = run_app(&mut terminal, &mut app);
+    restore_terminal()?;
+
+    app.stop_recording()?;
+
+    res.map(|()| ExitCode::SUCCESS)
+}
 
-    // restore terminal
-    disable_raw_mode().unwrap();
+fn setup_terminal() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    Terminal::new(backend)
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
     execute!(
-        terminal.backend_mut(),
+        io::stdout(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        Show
     )
-    .unwrap();
-    terminal.show_cursor().unwrap();
-
-    Ok(())
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
-    let mut start = Instant::now();
-    let mut tickindex = 0usize;
-    let mut ticksum = 0f32;
-    let mut ticklist = [0f32; 100];
+// new audio is analyzed as soon as it arrives and input is handled as soon as it is typed,
+// either only marks the screen as outdated; it is redrawn once the next frame is due, so the
+// frame rate stays at or below --fps, except after a resize which is redrawn right away
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+    let events = Events::new();
+    app.notify_frames(events.sender());
 
-    let tick_rate = Duration::from_millis(1000 / u64::try_from(app.args.fps).unwrap());
-    let events = Events::new(tick_rate);
+    let mut last_frame = Instant::now();
+    let mut next_frame = last_frame;
+    let mut outdated = true;
 
     loop {
-        terminal.draw(|f| ui(f, &app)).unwrap();
-        let newtick = start.elapsed().as_secs_f32();
-        app.args.fps =
-            (1f32 / calc_avg_tick(&mut tickindex, &mut ticksum, &mut ticklist, newtick)) as u8;
-        start = Instant::now();
-
-        let result = match events.next().unwrap() {
-            InputEvent::Input(key) => key,
-            InputEvent::Tick => Key::Unknown,
-        };
-
-        if result.is_unknown() {
-            app.update_state();
+        let now = Instant::now();
+        if outdated && now >= next_frame {
+            draw(terminal, app)?;
+            app.perf.frame.push(now - last_frame);
+            last_frame = now;
+            if app.perf.frame.window_done() {
+                log::debug!(
+                    "{:.1} fps (target {}), slowest of the last frames {:.1} ms",
+                    app.perf.fps(),
+                    app.args.fps,
+                    app.perf.frame.max().as_secs_f64() * 1000.
+                );
+            }
+            // a frame that ran late moves the schedule instead of being caught up in a burst
+            next_frame = (next_frame + frame_interval(app.args.fps)).max(now);
+            outdated = false;
         }
 
-        if result.is_right_arrow() {
-            let windows = [
-                WindowType::Hanning,
-                WindowType::Hamming,
-                WindowType::Blackman,
-                WindowType::Nuttall,
-            ];
-            let new_idx = ((app.fft_engine.get_window() as usize) + 1) % 4;
-            app.fft_engine.set_window(windows[new_idx].clone());
-        }
+        let timeout = if outdated {
+            next_frame.saturating_duration_since(Instant::now())
+        } else {
+            IDLE_INTERVAL
+        };
+        let key = match events.next(timeout).map_err(io::Error::other)? {
+            Some(InputEvent::Input(key)) => key,
+            Some(InputEvent::Mouse(mouse)) => {
+                let size = terminal.size()?;
+                handle_mouse(app, mouse, Rect::new(0, 0, size.width, size.height));
+                outdated = true;
+                continue;
+            }
+            Some(InputEvent::Resize) => {
+                draw(terminal, app)?;
+                continue;
+            }
+            Some(InputEvent::AudioFrame) => {
+                app.update_state();
+                outdated = true;
+                continue;
+            }
+            // nothing arrived in time, the stream is still checked so a lost device is
+            // noticed and its status shown without any audio coming in
+            None => {
+                app.check_stream();
+                outdated = true;
+                continue;
+            }
+        };
+        outdated = true;
 
-        if result.is_left_arrow() {
-            if !app.edit_in_device {
-                app.in_devices = cpal::default_host().input_devices().unwrap().collect();
+        // while the help is open the movement keys scroll it and every other key but the
+        // ones closing it is ignored
+        if app.show_help {
+            let size = terminal.size()?;
+            let size = Rect::new(0, 0, size.width, size.height);
+            let max = ui::max_help_scroll(app, size);
+            let page = ui::help_rows(size).max(1);
+            let scroll = app.help_scroll.min(max);
+            match app.keymap.action(&key) {
+                Some(Action::Next) => app.help_scroll = (scroll + 1).min(max),
+                Some(Action::Previous) => app.help_scroll = scroll.saturating_sub(1),
+                Some(Action::PageDown) => app.help_scroll = (scroll + page).min(max),
+                Some(Action::PageUp) => app.help_scroll = scroll.saturating_sub(page),
+                Some(Action::Help) => app.show_help = false,
+                _ if key == Key::Esc => app.show_help = false,
+                _ => {}
             }
-            app.edit_in_device = !app.edit_in_device;
+            continue;
         }
 
-        if result.is_down_arrow() {
-            if app.edit_in_device {
-                app.in_devices_idx = (app.in_devices_idx + 1) % app.in_devices.len();
+        // while the device list of the picker has focus typing filters it, so these keys
+        // don't reach their bindings
+        if app.edit_in_device && !app.edit_host {
+            match key {
+                Key::Char(c) => {
+                    let filter = format!("{}{c}", app.device_filter);
+                    app.set_device_filter(filter);
+                    continue;
+                }
+                Key::Backspace => {
+                    let mut filter = app.device_filter.clone();
+                    filter.pop();
+                    app.set_device_filter(filter);
+                    continue;
+                }
+                // clears the filter first and closes the picker once it is empty
+                Key::Esc => {
+                    if app.device_filter.is_empty() {
+                        app.edit_in_device = false;
+                    } else {
+                        app.set_device_filter(String::new());
+                    }
+                    continue;
+                }
+                _ => {}
             }
         }
 
-        if result.is_enter() {
-            if app.edit_in_device {
-                app.update_input_device();
-                app.edit_in_device = !app.edit_in_device;
-            }
+        // Esc closes whatever is open on top of the spectrum first, and only quits once
+        // nothing is
+        if key == Key::Esc && app.close_overlay() {
+            continue;
         }
 
-        if result.is_exit() {
-            break;
+        match app.keymap.action(&key) {
+            Some(Action::NextWindow) => app.adjust_setting(Setting::Window, 1),
+            Some(Action::PreviousWindow) => app.adjust_setting(Setting::Window, -1),
+            Some(Action::ToggleDevicePicker) => {
+                if app.edit_in_device {
+                    app.edit_in_device = false;
+                } else {
+                    app.open_picker()?;
+                }
+            }
+            Some(Action::SwitchPickerFocus) if app.edit_in_device => {
+                app.edit_host = !app.edit_host;
+            }
+            Some(Action::Next) if app.edit_in_device => {
+                if app.edit_host {
+                    app.hosts_idx = (app.hosts_idx + 1) % app.hosts.len();
+                } else {
+                    app.step_device(1);
+                }
+            }
+            Some(Action::Previous) if app.edit_in_device => {
+                if app.edit_host {
+                    app.hosts_idx = (app.hosts_idx + app.hosts.len() - 1) % app.hosts.len();
+                } else {
+                    app.step_device(-1);
+                }
+            }
+            Some(Action::PageDown) if app.edit_in_device && !app.edit_host => {
+                app.step_device(PICKER_PAGE);
+            }
+            Some(Action::PageUp) if app.edit_in_device && !app.edit_host => {
+                app.step_device(-PICKER_PAGE);
+            }
+            Some(Action::Next) if app.show_settings => {
+                app.settings_idx = (app.settings_idx + 1) % Setting::ALL.len();
+            }
+            Some(Action::Previous) if app.show_settings => {
+                app.settings_idx = (app.settings_idx + Setting::ALL.len() - 1) % Setting::ALL.len();
+            }
+            Some(Action::ToggleSettings) => {
+                app.show_settings = !app.show_settings;
+                app.edit_in_device = false;
+            }
+            Some(Action::Increase) if app.show_settings => {
+                app.adjust_setting(Setting::ALL[app.settings_idx], 1);
+            }
+            Some(Action::Decrease) if app.show_settings => {
+                app.adjust_setting(Setting::ALL[app.settings_idx], -1);
+            }
+            Some(Action::FftSizeUp) => app.adjust_setting(Setting::FftSize, 1),
+            Some(Action::FftSizeDown) => app.adjust_setting(Setting::FftSize, -1),
+            Some(Action::SmoothingUp) => app.adjust_setting(Setting::Smoothing, 1),
+            Some(Action::SmoothingDown) => app.adjust_setting(Setting::Smoothing, -1),
+            Some(Action::NextDisplayMode) => app.adjust_setting(Setting::DisplayMode, 1),
+            Some(Action::NextMarker) => app.adjust_setting(Setting::Marker, 1),
+            Some(Action::NextPane) => app.next_pane(),
+            Some(Action::NextLayout) => app.next_preset(),
+            Some(Action::ZoomIn) => app.zoom(ZOOM_STEP, app.cursor_freq),
+            Some(Action::ZoomOut) => app.zoom(1. / ZOOM_STEP, app.cursor_freq),
+            Some(Action::PanLeft) => app.pan(-PAN_STEP),
+            Some(Action::PanRight) => app.pan(PAN_STEP),
+            Some(Action::ResetView) => app.reset_view(),
+            Some(Action::Freeze) => app.toggle_freeze(),
+            Some(Action::StoreSnapshot) => app.store_snapshot(),
+            Some(Action::ClearSnapshots) => app.clear_snapshots(),
+            Some(Action::NextReference) => app.next_reference(),
+            Some(Action::ToggleDifference) => app.toggle_difference(),
+            Some(Action::NextTheme) => app.next_theme(),
+            Some(Action::PickerSelect) if app.edit_in_device => {
+                if app.edit_host {
+                    if let Err(err) = app.update_host() {
+                        app.status = Some(err.to_string());
+                    }
+                    app.edit_host = false;
+                } else if app.visible_devices().contains(&app.in_devices_idx) {
+                    if let Err(err) = app.update_input_device() {
+                        app.status = Some(err.to_string());
+                    }
+                    app.edit_in_device = !app.edit_in_device;
+                }
+            }
+            Some(Action::Trigger) if !app.pretrigger.is_enabled() => {
+                app.status = Some("pre-trigger capture is off, enable it with --pretrigger".into());
+            }
+            Some(Action::Trigger) => app.pretrigger.fire(),
+            Some(Action::ToggleRecording) => {
+                if let Err(err) = app.toggle_recording() {
+                    app.status = Some(err.to_string());
+                }
+            }
+            Some(Action::SaveConfig) => {
+                if let Err(err) = app.save_config() {
+                    app.status = Some(err.to_string());
+                }
+            }
+            Some(Action::Help) => {
+                app.show_help = true;
+                app.help_scroll = 0;
+            }
+            Some(Action::TogglePerf) => app.show_perf = !app.show_perf,
+            Some(Action::Quit) => break,
+            _ => {}
         }
     }
 
     Ok(())
 }
 
-fn calc_avg_tick(
-    tickindex: &mut usize,
-    ticksum: &mut f32,
-    ticklist: &mut [f32],
-    newtick: f32,
-) -> f32 {
-    *ticksum -= ticklist[*tickindex];
-    *ticksum += newtick;
-    ticklist[*tickindex] = newtick;
-    *tickindex = (*tickindex + 1) % 100;
-
-    *ticksum / (ticklist.len() as f32)
+fn draw<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+    let started = Instant::now();
+    terminal.draw(|f| ui(f, app))?;
+    app.perf.render.push(started.elapsed());
+    log::trace!("frame drawn in {:?}", started.elapsed());
+    Ok(())
+}
+
+// how often the screen is redrawn when neither audio nor input arrive
+const IDLE_INTERVAL: Duration = Duration::from_millis(250);
+
+// how many devices page up and page down move in the picker
+const PICKER_PAGE: i32 = 10;
+
+// how much of the view one zoom or pan step covers
+const ZOOM_STEP: f64 = 0.8;
+const PAN_STEP: f64 = 0.1;
+
+// a click focuses the pane under the mouse, the wheel zooms around the frequency under it and
+// pans with shift held, as long as the pane has a frequency axis
+fn handle_mouse(app: &mut App, mouse: MouseEvent, area: Rect) {
+    let hovered = app.presets[app.preset_idx]
+        .areas(area)
+        .into_iter()
+        .enumerate()
+        .find(|(_, pane)| pane.contains(Position::new(mouse.column, mouse.row)));
+    let Some((idx, pane_area)) = hovered else {
+        app.cursor_freq = None;
+        return;
+    };
+    if let MouseEventKind::Down(_) = mouse.kind {
+        app.focus = idx;
+    }
+    if !app.panes[idx].view.has_freq_axis() {
+        app.cursor_freq = None;
+        return;
+    }
+
+    app.cursor_freq = ui::freq_at(app, pane_area, mouse.column);
+    let shift = mouse.modifiers.contains(KeyModifiers::SHIFT);
+    match mouse.kind {
+        MouseEventKind::ScrollUp if shift => app.pan(-PAN_STEP),
+        MouseEventKind::ScrollDown if shift => app.pan(PAN_STEP),
+        MouseEventKind::ScrollUp => app.zoom(ZOOM_STEP, app.cursor_freq),
+        MouseEventKind::ScrollDown => app.zoom(1. / ZOOM_STEP, app.cursor_freq),
+        MouseEventKind::ScrollLeft => app.pan(-PAN_STEP),
+        MouseEventKind::ScrollRight => app.pan(PAN_STEP),
+        _ => {}
+    }
 }
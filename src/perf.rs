@@ -0,0 +1,125 @@
+use crate::capture::XrunCount;
+
+use std::{collections::VecDeque, time::Duration};
+
+// how many of the latest measurements the averages cover
+const WINDOW: usize = 100;
+
+// the latest durations of something that happens every frame
+#[derive(Default)]
+pub struct Timing {
+    samples: VecDeque<Duration>,
+    // every measurement so far, including the ones that left the window
+    count: usize,
+}
+
+impl Timing {
+    pub fn push(&mut self, duration: Duration) {
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(duration);
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.samples.iter().sum::<Duration>() / self.samples.len().max(1) as u32
+    }
+
+    pub fn max(&self) -> Duration {
+        self.samples.iter().max().copied().unwrap_or_default()
+    }
+
+    // true every time another full window of measurements came in
+    pub fn window_done(&self) -> bool {
+        self.count.is_multiple_of(WINDOW)
+    }
+}
+
+// what the performance overlay shows, measured while running
+#[derive(Default)]
+pub struct PerfStats {
+    // from the start of one frame to the start of the next
+    pub frame: Timing,
+    pub render: Timing,
+    // windowing and FFT of one block
+    pub fft: Timing,
+    // between two buffers from the audio device
+    pub callback: Timing,
+    // how much of the analysis block holds captured samples
+    pub buffer_fill: f64,
+    // since the stream started
+    pub xruns: XrunCount,
+}
+
+impl PerfStats {
+    // averaged over the last frames, `args.fps` is only the target
+    pub fn fps(&self) -> f64 {
+        let frame = self.frame.mean().as_secs_f64();
+        if frame > 0. {
+            1. / frame
+        } else {
+            0.
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn empty_timings_are_zero() {
+        let timing = Timing::default();
+        assert_eq!(timing.mean(), Duration::ZERO);
+        assert_eq!(timing.max(), Duration::ZERO);
+        assert_eq!(PerfStats::default().fps(), 0.);
+    }
+
+    #[test]
+    fn averages_cover_the_latest_window() {
+        let mut timing = Timing::default();
+        timing.push(ms(500));
+        for _ in 0..WINDOW - 1 {
+            timing.push(ms(10));
+        }
+        assert_eq!(timing.max(), ms(500));
+        assert_eq!(timing.mean(), ms(500 + 990) / WINDOW as u32);
+
+        // the slow one drops out once the window moved past it
+        timing.push(ms(20));
+        assert_eq!(timing.max(), ms(20));
+        assert_eq!(timing.mean(), ms(990 + 20) / WINDOW as u32);
+    }
+
+    #[test]
+    fn windows_are_done_every_hundred_measurements() {
+        let mut timing = Timing::default();
+        let done: Vec<usize> = (1..=3 * WINDOW)
+            .filter(|_| {
+                timing.push(ms(1));
+                timing.window_done()
+            })
+            .collect();
+        assert_eq!(done, [WINDOW, 2 * WINDOW, 3 * WINDOW]);
+    }
+
+    #[test]
+    fn fps_from_the_frame_interval() {
+        let mut perf = PerfStats::default();
+        perf.frame.push(ms(40));
+        perf.frame.push(ms(10));
+        assert_eq!(perf.fps(), 40.);
+    }
+
+    #[test]
+    fn frame_intervals_from_the_target_rate() {
+        assert_eq!(crate::app::frame_interval(50), ms(20));
+        // 0 would never draw, it is treated as 1
+        assert_eq!(crate::app::frame_interval(0), Duration::from_secs(1));
+    }
+}
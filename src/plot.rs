@@ -0,0 +1,465 @@
+//! A canvas widget with its own rasterizer, so the resolution of the spectrum doesn't depend
+//! on the markers ratatui's [`Canvas`](ratatui::widgets::canvas::Canvas) supports.
+//!
+//! Every terminal cell is split into a grid of pixels, from a single one with [`Marker::Block`]
+//! up to 2x4 with [`Marker::Braille`], and drawn with the character that lights exactly the
+//! pixels that were set.
+
+use std::str::FromStr;
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Color,
+    widgets::{Block, Widget},
+};
+
+/// The characters a [`Plot`] draws with, which decide how many pixels each cell holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Marker {
+    /// One pixel per cell drawn as `•`.
+    Dot,
+    /// One pixel per cell filling the whole cell.
+    Block,
+    /// Two pixels per cell stacked vertically, drawn with `▀`, `▄` and `█`.
+    HalfBlock,
+    /// 2x2 pixels per cell, drawn with the quadrant block characters.
+    Quadrant,
+    /// 2x3 pixels per cell, drawn with the sextant characters of Unicode 13, which not every
+    /// font has.
+    Sextant,
+    /// 2x4 pixels per cell, drawn with Braille patterns.
+    Braille,
+}
+
+// the Braille dot for every pixel, indexed by row and column
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// indexed by the pattern with the top left pixel in bit 0, then top right, bottom left and
+// bottom right
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+impl Marker {
+    /// All markers, from the lowest to the highest resolution.
+    pub const ALL: [Marker; 6] = [
+        Marker::Dot,
+        Marker::Block,
+        Marker::HalfBlock,
+        Marker::Quadrant,
+        Marker::Sextant,
+        Marker::Braille,
+    ];
+
+    /// The lowercase name accepted by [`Marker::from_str`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Marker::Dot => "dot",
+            Marker::Block => "block",
+            Marker::HalfBlock => "halfblock",
+            Marker::Quadrant => "quadrant",
+            Marker::Sextant => "sextant",
+            Marker::Braille => "braille",
+        }
+    }
+
+    /// Pixels per cell as columns and rows.
+    pub fn resolution(&self) -> (u16, u16) {
+        match self {
+            Marker::Dot | Marker::Block => (1, 1),
+            Marker::HalfBlock => (1, 2),
+            Marker::Quadrant => (2, 2),
+            Marker::Sextant => (2, 3),
+            Marker::Braille => (2, 4),
+        }
+    }
+
+    // the bit a pixel sets in the pattern of its cell
+    fn bit(&self, col: u16, row: u16) -> u8 {
+        match self {
+            Marker::Braille => BRAILLE_DOTS[usize::from(row)][usize::from(col)],
+            _ => 1 << (row * self.resolution().0 + col),
+        }
+    }
+
+    fn symbol(&self, pattern: u8) -> char {
+        match self {
+            Marker::Dot => '•',
+            Marker::Block => '█',
+            Marker::HalfBlock => [' ', '▀', '▄', '█'][usize::from(pattern)],
+            Marker::Quadrant => QUADRANTS[usize::from(pattern)],
+            // the sextants run from U+1FB00 in pattern order, leaving out the empty and full
+            // cell and the two halves that already exist as block elements
+            Marker::Sextant => match pattern {
+                0 => ' ',
+                21 => '▌',
+                42 => '▐',
+                63 => '█',
+                n => {
+                    let skipped = u32::from(n > 21) + u32::from(n > 42);
+                    char::from_u32(0x1FB00 + u32::from(n) - 1 - skipped).unwrap_or('█')
+                }
+            },
+            Marker::Braille => char::from_u32(0x2800 + u32::from(pattern)).unwrap_or('⣿'),
+        }
+    }
+}
+
+impl FromStr for Marker {
+    type Err = String;
+
+    /// Parses the names returned by [`Marker::name`], ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Marker::ALL
+            .into_iter()
+            .find(|marker| marker.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown marker: {s}"))
+    }
+}
+
+/// Draws shapes given in plot coordinates onto the pixels of a [`Plot`].
+///
+/// Later drawing wins, both for the pixels it sets and for the color of their cells.
+pub struct Painter {
+    marker: Marker,
+    x_bounds: [f64; 2],
+    y_bounds: [f64; 2],
+    // in pixels
+    width: u16,
+    height: u16,
+    // the pattern and color of every cell, row by row
+    cells: Vec<(u8, Color)>,
+}
+
+impl Painter {
+    fn new(marker: Marker, x_bounds: [f64; 2], y_bounds: [f64; 2], area: Rect) -> Painter {
+        let (cols, rows) = marker.resolution();
+        Painter {
+            marker,
+            x_bounds,
+            y_bounds,
+            width: area.width * cols,
+            height: area.height * rows,
+            cells: vec![(0, Color::Reset); usize::from(area.width) * usize::from(area.height)],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    // the pixel column of `x`, which has to be inside the bounds
+    fn column(&self, x: f64) -> u16 {
+        let [left, right] = self.x_bounds;
+        let position = (x - left) / (right - left) * f64::from(self.width);
+        (position as u16).min(self.width - 1)
+    }
+
+    // the pixel row of `y` counted from the top, `y` has to be inside the bounds
+    fn row(&self, y: f64) -> u16 {
+        let [bottom, top] = self.y_bounds;
+        let position = (top - y) / (top - bottom) * f64::from(self.height);
+        (position as u16).min(self.height - 1)
+    }
+
+    fn set(&mut self, col: u16, row: u16, color: Color) {
+        let (cols, rows) = self.marker.resolution();
+        let idx =
+            usize::from(row / rows) * usize::from(self.width / cols) + usize::from(col / cols);
+        let cell = &mut self.cells[idx];
+        cell.0 |= self.marker.bit(col % cols, row % rows);
+        cell.1 = color;
+    }
+
+    // the part of the line from (x1, y1) to (x2, y2) inside the bounds, after Liang and Barsky
+    fn clip(&self, x1: f64, y1: f64, x2: f64, y2: f64) -> Option<[f64; 4]> {
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let (mut enter, mut exit) = (0f64, 1f64);
+        for (p, q) in [
+            (-dx, x1 - self.x_bounds[0]),
+            (dx, self.x_bounds[1] - x1),
+            (-dy, y1 - self.y_bounds[0]),
+            (dy, self.y_bounds[1] - y1),
+        ] {
+            if p == 0. {
+                if q < 0. {
+                    return None;
+                }
+                continue;
+            }
+            let t = q / p;
+            if p < 0. {
+                enter = enter.max(t);
+            } else {
+                exit = exit.min(t);
+            }
+            if enter > exit {
+                return None;
+            }
+        }
+        Some([
+            x1 + enter * dx,
+            y1 + enter * dy,
+            x1 + exit * dx,
+            y1 + exit * dy,
+        ])
+    }
+
+    /// Sets the pixel at (`x`, `y`), unless it is outside of the plot.
+    pub fn point(&mut self, x: f64, y: f64, color: Color) {
+        let inside = |v: f64, [low, high]: [f64; 2]| v >= low && v <= high;
+        if self.is_empty() || !inside(x, self.x_bounds) || !inside(y, self.y_bounds) {
+            return;
+        }
+        self.set(self.column(x), self.row(y), color);
+    }
+
+    /// Draws a line from (`x1`, `y1`) to (`x2`, `y2`), clipped to the plot.
+    pub fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, color: Color) {
+        if self.is_empty() {
+            return;
+        }
+        let Some([x1, y1, x2, y2]) = self.clip(x1, y1, x2, y2) else {
+            return;
+        };
+        // Bresenham between the two end pixels
+        let (mut col, mut row) = (i32::from(self.column(x1)), i32::from(self.row(y1)));
+        let (end_col, end_row) = (i32::from(self.column(x2)), i32::from(self.row(y2)));
+        let (dx, dy) = ((end_col - col).abs(), -(end_row - row).abs());
+        let (step_col, step_row) = ((end_col - col).signum(), (end_row - row).signum());
+        let mut error = dx + dy;
+        loop {
+            self.set(col as u16, row as u16, color);
+            if col == end_col && row == end_row {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                col += step_col;
+            }
+            if doubled <= dx {
+                error += dx;
+                row += step_row;
+            }
+        }
+    }
+
+    /// Fills the area between the line from (`x1`, `y1`) to (`x2`, `y2`) and the bottom of the
+    /// plot, every pixel column reaching up to the highest point of the line above it.
+    pub fn area(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, color: Color) {
+        let [left, right] = self.x_bounds;
+        let (start, end) = (x1.min(x2).max(left), x1.max(x2).min(right));
+        if self.is_empty() || start > end {
+            return;
+        }
+        let level = |x: f64| {
+            if x2 == x1 {
+                y1.max(y2)
+            } else {
+                y1 + (y2 - y1) * (x - x1) / (x2 - x1)
+            }
+        };
+        let column_width = (right - left) / f64::from(self.width);
+        for col in self.column(start)..=self.column(end) {
+            // the line is straight, so its highest point over a column is at one of the edges
+            let edge = left + column_width * f64::from(col);
+            let top =
+                level(edge.clamp(start, end)).max(level((edge + column_width).clamp(start, end)));
+            if top < self.y_bounds[0] {
+                continue;
+            }
+            let top_row = self.row(top.min(self.y_bounds[1]));
+            for row in top_row..self.height {
+                self.set(col, row, color);
+            }
+        }
+    }
+}
+
+/// A widget drawing through a [`Painter`] onto a plane with the given bounds.
+pub struct Plot<'a, F>
+where
+    F: Fn(&mut Painter),
+{
+    block: Option<Block<'a>>,
+    marker: Marker,
+    x_bounds: [f64; 2],
+    y_bounds: [f64; 2],
+    paint: F,
+}
+
+impl Default for Plot<'_, fn(&mut Painter)> {
+    fn default() -> Self {
+        Plot {
+            block: None,
+            marker: Marker::Braille,
+            x_bounds: [0., 1.],
+            y_bounds: [0., 1.],
+            paint: |_| {},
+        }
+    }
+}
+
+impl<'a, F> Plot<'a, F>
+where
+    F: Fn(&mut Painter),
+{
+    /// Surrounds the plot with `block`.
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Sets the characters the plot is drawn with, Braille by default.
+    pub fn marker(mut self, marker: Marker) -> Self {
+        self.marker = marker;
+        self
+    }
+
+    /// Sets the values at the left and right edge.
+    pub fn x_bounds(mut self, bounds: [f64; 2]) -> Self {
+        self.x_bounds = bounds;
+        self
+    }
+
+    /// Sets the values at the bottom and top edge.
+    pub fn y_bounds(mut self, bounds: [f64; 2]) -> Self {
+        self.y_bounds = bounds;
+        self
+    }
+
+    /// Sets the function drawing the content, called every time the plot is rendered.
+    pub fn paint<G>(self, paint: G) -> Plot<'a, G>
+    where
+        G: Fn(&mut Painter),
+    {
+        Plot {
+            block: self.block,
+            marker: self.marker,
+            x_bounds: self.x_bounds,
+            y_bounds: self.y_bounds,
+            paint,
+        }
+    }
+}
+
+impl<F> Widget for Plot<'_, F>
+where
+    F: Fn(&mut Painter),
+{
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = match &self.block {
+            Some(block) => {
+                block.render(area, buf);
+                block.inner(area)
+            }
+            None => area,
+        };
+        let [left, right] = self.x_bounds;
+        let [bottom, top] = self.y_bounds;
+        if area.is_empty() || left >= right || bottom >= top {
+            return;
+        }
+
+        let mut painter = Painter::new(self.marker, self.x_bounds, self.y_bounds, area);
+        (self.paint)(&mut painter);
+
+        for (idx, (pattern, color)) in painter.cells.into_iter().enumerate() {
+            if pattern == 0 {
+                continue;
+            }
+            let x = area.x + (idx % usize::from(area.width)) as u16;
+            let y = area.y + (idx / usize::from(area.width)) as u16;
+            if let Some(cell) = buf.cell_mut((x, y)) {
+                cell.set_char(self.marker.symbol(pattern)).set_fg(color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 4x4 plot of block cells, so every cell is one pixel
+    fn painter() -> Painter {
+        Painter::new(Marker::Block, [0., 4.], [0., 4.], Rect::new(0, 0, 4, 4))
+    }
+
+    fn pixels(painter: &Painter) -> Vec<String> {
+        painter
+            .cells
+            .chunks(4)
+            .map(|row| {
+                row.iter()
+                    .map(|(pattern, _)| if *pattern == 0 { '.' } else { '#' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lines_are_clipped_to_the_bounds() {
+        let painter = Painter::new(Marker::Braille, [0., 10.], [0., 10.], Rect::new(0, 0, 5, 5));
+        assert_eq!(painter.clip(-5., 5., 15., 5.), Some([0., 5., 10., 5.]));
+        assert_eq!(painter.clip(-5., -5., 5., 5.), Some([0., 0., 5., 5.]));
+        // entirely inside
+        assert_eq!(painter.clip(1., 2., 3., 4.), Some([1., 2., 3., 4.]));
+        // parallel to an edge and outside of it
+        assert_eq!(painter.clip(11., 0., 11., 10.), None);
+        // crossing the corner region without entering
+        assert_eq!(painter.clip(-5., 8., 5., 18.), None);
+    }
+
+    #[test]
+    fn areas_fill_down_to_the_bottom() {
+        let mut painter = painter();
+        painter.area(0., 0., 4., 4., Color::White);
+        assert_eq!(pixels(&painter), ["...#", "..##", ".###", "####"]);
+    }
+
+    #[test]
+    fn areas_outside_of_the_bounds() {
+        let mut painter = painter();
+        // only the part right of the left edge is drawn
+        painter.area(-2., 2., 1.5, 2., Color::White);
+        // below the plot nothing is filled
+        painter.area(2., -1., 4., -1., Color::White);
+        assert_eq!(pixels(&painter), ["....", "....", "##..", "##.."]);
+    }
+
+    #[test]
+    fn sextants_skip_the_existing_half_blocks() {
+        let symbol = |pattern| Marker::Sextant.symbol(pattern);
+        assert_eq!(symbol(0), ' ');
+        assert_eq!(symbol(1), '\u{1FB00}');
+        assert_eq!(symbol(20), '\u{1FB13}');
+        assert_eq!(symbol(21), '▌');
+        assert_eq!(symbol(22), '\u{1FB14}');
+        assert_eq!(symbol(41), '\u{1FB27}');
+        assert_eq!(symbol(42), '▐');
+        assert_eq!(symbol(43), '\u{1FB28}');
+        assert_eq!(symbol(62), '\u{1FB3B}');
+        assert_eq!(symbol(63), '█');
+
+        // the other 60 patterns use every sextant once
+        let mut sextants: Vec<char> = (1..63)
+            .filter(|n| ![21, 42].contains(n))
+            .map(symbol)
+            .collect();
+        sextants.dedup();
+        assert_eq!(sextants.len(), 60);
+        assert!(sextants.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn pixels_set_bits_of_their_cell() {
+        let mut painter = Painter::new(Marker::Quadrant, [0., 2.], [0., 2.], Rect::new(0, 0, 1, 1));
+        painter.point(0.2, 1.8, Color::White);
+        painter.point(1.8, 0.2, Color::Red);
+        assert_eq!(painter.cells, [(0b1001, Color::Red)]);
+        assert_eq!(Marker::Quadrant.symbol(0b1001), '▚');
+    }
+}
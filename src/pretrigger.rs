@@ -0,0 +1,359 @@
+use crate::{
+    error::Result,
+    recorder,
+    wav::{self, RecordFormat},
+};
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use hound::WavWriter;
+use serde::Serialize;
+
+// fires when the energy summed over a frequency band rises above a level
+pub struct BandTrigger {
+    pub level_db: f64,
+    pub min_freq: f64,
+    pub max_freq: f64,
+}
+
+impl BandTrigger {
+    fn is_triggered(&self, bins: &[f64], freq_step: f64) -> bool {
+        let power: f64 = bins
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (self.min_freq..=self.max_freq).contains(&(freq_step * *i as f64)))
+            .map(|(_, db)| 10f64.powf(db / 10.))
+            .sum();
+        10. * power.log10() > self.level_db
+    }
+}
+
+#[derive(Serialize)]
+struct DumpFrame {
+    // seconds relative to the trigger, negative values are from before it
+    time: f64,
+    bins: Vec<f64>,
+}
+
+#[derive(Serialize)]
+struct Dump {
+    trigger_unix_time: f64,
+    freq_step: f64,
+    frames: Vec<DumpFrame>,
+}
+
+// keeps the spectrum frames of the last few seconds around, and once a trigger fired waits
+// for the post-trigger time to pass before handing everything over to be saved
+pub struct PreTrigger {
+    pub pre: Duration,
+    pub post: Duration,
+    pub trigger: Option<BandTrigger>,
+    // frames closer together than this are not kept, the spectrum is analyzed for every
+    // buffer from the device but a dump only needs as many frames as were drawn
+    pub frame_interval: Duration,
+    frames: VecDeque<(Instant, Vec<f64>)>,
+    triggered_at: Option<(Instant, SystemTime)>,
+    // the band trigger only fires again once the level dropped back below the threshold
+    armed: bool,
+}
+
+impl PreTrigger {
+    pub fn new(
+        pre: Duration,
+        post: Duration,
+        frame_interval: Duration,
+        trigger: Option<BandTrigger>,
+    ) -> Self {
+        PreTrigger {
+            pre,
+            post,
+            trigger,
+            frame_interval,
+            frames: VecDeque::new(),
+            triggered_at: None,
+            armed: true,
+        }
+    }
+
+    // without any pre-trigger time there is nothing to keep, so capture is off
+    pub fn is_enabled(&self) -> bool {
+        !self.pre.is_zero()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.triggered_at.is_some()
+    }
+
+    // how many seconds of input the audio callback has to keep around for a dump
+    pub fn history_secs(&self) -> f64 {
+        // one extra second covers the time between the post-trigger deadline and the next frame
+        (self.pre + self.post).as_secs_f64() + 1.
+    }
+
+    pub fn fire(&mut self) {
+        if self.is_enabled() && self.triggered_at.is_none() {
+            self.triggered_at = Some((Instant::now(), SystemTime::now()));
+        }
+    }
+
+    pub fn push_frame(&mut self, bins: &[f64], freq_step: f64) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let now = Instant::now();
+        let due = self
+            .frames
+            .back()
+            .is_none_or(|(t, _)| now.duration_since(*t) >= self.frame_interval);
+        if due {
+            self.frames.push_back((now, bins.to_vec()));
+        }
+        let keep = self.pre + self.post;
+        while let Some((t, _)) = self.frames.front() {
+            if now.duration_since(*t) <= keep {
+                break;
+            }
+            self.frames.pop_front();
+        }
+
+        let Some(trigger) = &self.trigger else {
+            return;
+        };
+        let triggered = trigger.is_triggered(bins, freq_step);
+        if triggered && self.armed {
+            self.fire();
+        }
+        self.armed = !triggered;
+    }
+
+    // once the post-trigger time has passed, writes the audio and spectrum frames around the
+    // trigger on a background thread, `history` is the audio kept by the callback
+    pub fn poll(
+        &mut self,
+        history: &VecDeque<f32>,
+        sample_rate: u32,
+        freq_step: f64,
+    ) -> Option<PathBuf> {
+        let (triggered_at, wall_time) = self.triggered_at?;
+        if triggered_at.elapsed() < self.post {
+            return None;
+        }
+        self.triggered_at = None;
+
+        let span = triggered_at.elapsed() + self.pre;
+        let sample_count =
+            ((span.as_secs_f64() * f64::from(sample_rate)) as usize).min(history.len());
+        let samples: Vec<f32> = history
+            .iter()
+            .skip(history.len() - sample_count)
+            .copied()
+            .collect();
+
+        let frames = self
+            .frames
+            .iter()
+            .filter(|(t, _)| *t + self.pre >= triggered_at)
+            .map(|(t, bins)| DumpFrame {
+                time: if *t >= triggered_at {
+                    t.duration_since(triggered_at).as_secs_f64()
+                } else {
+                    -triggered_at.duration_since(*t).as_secs_f64()
+                },
+                bins: bins.clone(),
+            })
+            .collect();
+        let dump = Dump {
+            trigger_unix_time: wall_time
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default(),
+            freq_step,
+            frames,
+        };
+
+        let path = recorder::timestamped_path("trigger");
+        let wav_path = path.clone();
+        thread::spawn(move || {
+            if let Err(err) = write_dump(&wav_path, &samples, sample_rate, &dump) {
+                log::error!("writing {} failed: {err}", wav_path.display());
+            }
+        });
+        log::info!("trigger fired, dumping to {}", path.display());
+        Some(path)
+    }
+}
+
+fn write_dump(wav_path: &Path, samples: &[f32], sample_rate: u32, dump: &Dump) -> Result<()> {
+    let mut writer = WavWriter::create(wav_path, wav::spec(RecordFormat::F32, sample_rate))?;
+    wav::write_samples(&mut writer, RecordFormat::F32, samples)?;
+    writer.finalize()?;
+
+    let json = BufWriter::new(File::create(wav_path.with_extension("json"))?);
+    serde_json::to_writer(json, dump)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::app::Args;
+
+    use clap::Parser;
+    use hound::WavReader;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    // bins 100 Hz apart with a loud one at 500 Hz
+    fn tone_bins() -> Vec<f64> {
+        let mut bins = vec![-100.; 10];
+        bins[5] = -10.;
+        bins
+    }
+
+    fn band_trigger(level_db: f64) -> PreTrigger {
+        PreTrigger::new(
+            SECOND,
+            SECOND,
+            Duration::ZERO,
+            Some(BandTrigger {
+                level_db,
+                min_freq: 400.,
+                max_freq: 600.,
+            }),
+        )
+    }
+
+    #[test]
+    fn band_energy_against_the_level() {
+        let band = |min_freq, max_freq| BandTrigger {
+            level_db: -20.,
+            min_freq,
+            max_freq,
+        };
+        assert!(band(400., 600.).is_triggered(&tone_bins(), 100.));
+        assert!(band(500., 500.).is_triggered(&tone_bins(), 100.));
+        assert!(!band(600., 900.).is_triggered(&tone_bins(), 100.));
+        // ten bins at -30 dB add up to -20 dB, which is not above the level
+        assert!(!band(0., 1000.).is_triggered(&[-30.; 10], 100.));
+        assert!(band(0., 1000.).is_triggered(&[-29.; 10], 100.));
+    }
+
+    #[test]
+    fn nothing_is_kept_without_pretrigger_time() {
+        let mut pretrigger = PreTrigger::new(Duration::ZERO, SECOND, Duration::ZERO, None);
+        assert!(!pretrigger.is_enabled());
+        pretrigger.push_frame(&tone_bins(), 100.);
+        pretrigger.fire();
+        assert!(pretrigger.frames.is_empty());
+        assert!(!pretrigger.is_pending());
+    }
+
+    #[test]
+    fn frames_are_kept_at_the_frame_interval() {
+        let mut pretrigger = PreTrigger::new(SECOND, SECOND, Duration::from_secs(60), None);
+        for _ in 0..3 {
+            pretrigger.push_frame(&tone_bins(), 100.);
+        }
+        assert_eq!(pretrigger.frames.len(), 1);
+
+        pretrigger.frame_interval = Duration::ZERO;
+        for _ in 0..3 {
+            pretrigger.push_frame(&tone_bins(), 100.);
+        }
+        assert_eq!(pretrigger.frames.len(), 4);
+    }
+
+    #[test]
+    fn frames_older_than_pre_and_post_time_are_dropped() {
+        let short = Duration::from_millis(5);
+        let mut pretrigger = PreTrigger::new(short, short, Duration::ZERO, None);
+        pretrigger.push_frame(&tone_bins(), 100.);
+        thread::sleep(4 * short);
+        pretrigger.push_frame(&tone_bins(), 100.);
+        assert_eq!(pretrigger.frames.len(), 1);
+    }
+
+    #[test]
+    fn band_trigger_rearms_once_the_level_dropped() {
+        let mut pretrigger = band_trigger(-20.);
+        pretrigger.push_frame(&tone_bins(), 100.);
+        assert!(pretrigger.is_pending());
+
+        // the dump was written, but the level stayed up
+        pretrigger.triggered_at = None;
+        pretrigger.push_frame(&tone_bins(), 100.);
+        assert!(!pretrigger.is_pending());
+
+        pretrigger.push_frame(&[-100.; 10], 100.);
+        pretrigger.push_frame(&tone_bins(), 100.);
+        assert!(pretrigger.is_pending());
+
+        let mut quiet = band_trigger(0.);
+        quiet.push_frame(&tone_bins(), 100.);
+        assert!(!quiet.is_pending());
+    }
+
+    #[test]
+    fn a_dump_waits_for_the_posttrigger_time() {
+        let mut pretrigger = band_trigger(-20.);
+        pretrigger.fire();
+        assert!(pretrigger.is_pending());
+        assert_eq!(pretrigger.poll(&VecDeque::new(), 8000, 100.), None);
+        assert!(pretrigger.is_pending());
+    }
+
+    #[test]
+    fn dumps_hold_the_audio_and_the_frames() {
+        let path = std::env::temp_dir().join("audiolyzer-dump.wav");
+        let dump = Dump {
+            trigger_unix_time: 1.5,
+            freq_step: 100.,
+            frames: vec![DumpFrame {
+                time: -0.25,
+                bins: vec![-10., -20.],
+            }],
+        };
+        write_dump(&path, &[0.5, -0.5], 8000, &dump).unwrap();
+
+        let mut reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 8000);
+        let samples: Vec<f32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, [0.5, -0.5]);
+
+        let json = std::fs::read_to_string(path.with_extension("json")).unwrap();
+        assert_eq!(
+            json,
+            r#"{"trigger_unix_time":1.5,"freq_step":100.0,"frames":[{"time":-0.25,"bins":[-10.0,-20.0]}]}"#
+        );
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("json")).unwrap();
+    }
+
+    #[test]
+    fn trigger_arguments() {
+        let parse = |args: &[&str]| Args::try_parse_from([&["audiolyzer"], args].concat());
+        for secs in ["-1", "NaN", "inf", "abc"] {
+            assert!(parse(&["--pretrigger", secs]).is_err(), "{secs}");
+            assert!(
+                parse(&[&format!("--posttrigger={secs}")]).is_err(),
+                "{secs}"
+            );
+        }
+        let args = parse(&["--pretrigger", "2.5", "--posttrigger", "0"]).unwrap();
+        assert_eq!((args.pretrigger, args.posttrigger), (2.5, 0.));
+
+        // the level alone would never fire, as nothing is kept without --pretrigger
+        assert!(parse(&["--trigger-db", "-20"]).is_err());
+        let args = parse(&["--trigger-db", "-20", "--pretrigger", "1"]).unwrap();
+        assert_eq!(args.trigger_db, Some(-20.));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    error::{Error, Result},
+    wav::{self, RecordFormat},
+};
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use hound::WavWriter;
+
+// a recording in progress, the samples are written to disk on a background thread so the
+// audio callback only has to hand its buffer over a channel
+pub struct Recording {
+    pub path: PathBuf,
+    // the rate the WAV header was written with, every sample sent has to be at it
+    pub sample_rate: u32,
+    started: Instant,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl Recording {
+    // returns the recording together with the sender the audio callback should feed
+    pub fn start(
+        path: &Path,
+        format: RecordFormat,
+        sample_rate: u32,
+    ) -> Result<(Recording, Sender<Vec<f32>>)> {
+        let mut writer = WavWriter::create(path, wav::spec(format, sample_rate))?;
+        let (tx, rx) = channel::<Vec<f32>>();
+
+        // the loop ends once every sender has been dropped, which is how a recording is stopped
+        let handle = thread::spawn(move || {
+            for samples in rx {
+                wav::write_samples(&mut writer, format, &samples)?;
+            }
+            writer.finalize()?;
+            Ok(())
+        });
+
+        Ok((
+            Recording {
+                path: path.to_path_buf(),
+                sample_rate,
+                started: Instant::now(),
+                handle,
+            },
+            tx,
+        ))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    // waits for the writer to drain, the caller must have dropped every sender first
+    pub fn finish(self) -> Result<()> {
+        self.handle
+            .join()
+            .map_err(|_| Error::Stream("wav writer thread panicked".into()))?
+    }
+}
+
+// named after the current time in milliseconds, with a counter appended if that file exists
+// anyway, since writing a WAV file truncates what was there
+pub fn timestamped_path(prefix: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    unused_path(&format!("{prefix}-{millis}"))
+}
+
+fn unused_path(stem: &str) -> PathBuf {
+    let mut path = PathBuf::from(format!("{stem}.wav"));
+    let mut n = 1;
+    while path.exists() {
+        n += 1;
+        path = PathBuf::from(format!("{stem}-{n}.wav"));
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hound::{SampleFormat, WavReader};
+
+    fn record(name: &str, format: RecordFormat, blocks: &[&[f32]]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let (recording, tx) = Recording::start(&path, format, 8000).unwrap();
+        assert_eq!(recording.sample_rate, 8000);
+        for block in blocks {
+            tx.send(block.to_vec()).unwrap();
+        }
+        drop(tx);
+        recording.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn blocks_are_written_in_order() {
+        let path = record(
+            "audiolyzer-record-f32.wav",
+            RecordFormat::F32,
+            &[&[0., 0.25], &[], &[-0.5, 1.5]],
+        );
+        let mut reader = WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        assert_eq!(
+            (spec.channels, spec.sample_rate, spec.bits_per_sample),
+            (1, 8000, 32)
+        );
+        assert_eq!(spec.sample_format, SampleFormat::Float);
+        let samples: Vec<f32> = reader.samples().map(|s| s.unwrap()).collect();
+        std::fs::remove_file(path).unwrap();
+        // float samples are stored as they are, without clipping
+        assert_eq!(samples, [0., 0.25, -0.5, 1.5]);
+    }
+
+    #[test]
+    fn integer_formats_are_scaled_and_clipped() {
+        let block: &[f32] = &[0., 0.5, -1., 2.];
+        for (format, bits, expected) in [
+            (RecordFormat::I16, 16, [0, 16383, -32767, 32767]),
+            (RecordFormat::I24, 24, [0, 4194303, -8388607, 8388607]),
+        ] {
+            let path = record(&format!("audiolyzer-record-{bits}.wav"), format, &[block]);
+            let mut reader = WavReader::open(&path).unwrap();
+            assert_eq!(reader.spec().bits_per_sample, bits);
+            let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+            std::fs::remove_file(path).unwrap();
+            assert_eq!(samples, expected, "{bits} bits");
+        }
+    }
+
+    #[test]
+    fn paths_in_use_get_a_counter() {
+        let stem = std::env::temp_dir().join("audiolyzer-in-use");
+        let stem = stem.to_string_lossy();
+        let first = unused_path(&stem);
+        assert_eq!(first, PathBuf::from(format!("{stem}.wav")));
+
+        std::fs::write(&first, "").unwrap();
+        let second = unused_path(&stem);
+        assert_eq!(second, PathBuf::from(format!("{stem}-2.wav")));
+        std::fs::write(&second, "").unwrap();
+        assert_eq!(unused_path(&stem), PathBuf::from(format!("{stem}-3.wav")));
+
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn timestamps_have_millisecond_resolution() {
+        let prefix = std::env::temp_dir().join("audiolyzer-stamp");
+        let first = timestamped_path(&prefix.to_string_lossy());
+        thread::sleep(Duration::from_millis(2));
+        let second = timestamped_path(&prefix.to_string_lossy());
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(std::env::temp_dir().as_path()));
+    }
+
+    #[test]
+    fn unwritable_paths_fail_right_away() {
+        let path = std::env::temp_dir().join("audiolyzer-missing-dir/recording.wav");
+        assert!(Recording::start(&path, RecordFormat::F32, 8000).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use audiolyzer::{display::DisplayStrategyFactory, fft::WindowType, plot::Marker};
+
+// the analysis parameters that can be changed from the settings panel while running
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Setting {
+    Window,
+    FftSize,
+    SampleRate,
+    Smoothing,
+    ZeroPad,
+    ConstantQ,
+    DisplayMode,
+    Marker,
+    MinFreq,
+    MaxFreq,
+    Fps,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 11] = [
+        Setting::Window,
+        Setting::FftSize,
+        Setting::SampleRate,
+        Setting::Smoothing,
+        Setting::ZeroPad,
+        Setting::ConstantQ,
+        Setting::DisplayMode,
+        Setting::Marker,
+        Setting::MinFreq,
+        Setting::MaxFreq,
+        Setting::Fps,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Setting::Window => "Window",
+            Setting::FftSize => "FFT size",
+            Setting::SampleRate => "Sample rate",
+            Setting::Smoothing => "Smoothing",
+            Setting::ZeroPad => "Zero padding",
+            Setting::ConstantQ => "Constant-Q",
+            Setting::DisplayMode => "Display mode",
+            Setting::Marker => "Marker",
+            Setting::MinFreq => "Min frequency",
+            Setting::MaxFreq => "Max frequency",
+            Setting::Fps => "Target FPS",
+        }
+    }
+}
+
+// the rates offered when stepping, the device may still settle on a different one
+const SAMPLE_RATES: [u32; 11] = [
+    8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+
+// bins per octave offered when stepping, below the first one is a plain FFT
+const CONSTANT_Q_RESOLUTIONS: [u32; 5] = [12, 24, 36, 48, 96];
+
+// also the sizes --fft-size accepts
+pub const MIN_FFT_SIZE: u32 = 64;
+pub const MAX_FFT_SIZE: u32 = 65536;
+
+// in all of these `direction` is 1 to increase and -1 to decrease the value
+
+// a parameterized window steps to the next window type, which starts at its default parameter
+pub fn step_window(window: &WindowType, direction: i32) -> WindowType {
+    let windows = WindowType::ALL;
+    let idx = windows
+        .iter()
+        .position(|w| w.name() == window.name())
+        .unwrap_or(0) as i32;
+    windows[(idx + direction).rem_euclid(windows.len() as i32) as usize].clone()
+}
+
+// doubles or halves the size, which also rounds odd sizes given on the command line up to
+// a power of two
+pub fn step_fft_size(fft_size: u32, direction: i32) -> u32 {
+    let size = if direction > 0 {
+        fft_size.saturating_mul(2)
+    } else {
+        fft_size / 2
+    };
+    size.next_power_of_two().clamp(MIN_FFT_SIZE, MAX_FFT_SIZE)
+}
+
+pub fn step_sample_rate(sample_rate: u32, direction: i32) -> u32 {
+    let next = if direction > 0 {
+        SAMPLE_RATES.iter().find(|rate| **rate > sample_rate)
+    } else {
+        SAMPLE_RATES.iter().rev().find(|rate| **rate < sample_rate)
+    };
+    next.copied().unwrap_or(sample_rate)
+}
+
+// rounded to two decimals so repeated steps don't drift
+pub fn step_smoothing(smoothing: f64, direction: i32) -> f64 {
+    let smoothing = smoothing + 0.05 * f64::from(direction);
+    ((smoothing * 100.).round() / 100.).clamp(0., 0.99)
+}
+
+// doubles or halves the factor, within the range --zero-pad accepts
+pub fn step_zero_pad(factor: u32, direction: i32) -> u32 {
+    let factor = if direction > 0 {
+        factor.saturating_mul(2)
+    } else {
+        factor / 2
+    };
+    factor.next_power_of_two().clamp(1, 16)
+}
+
+// `None` is a plain FFT, values given on the command line step to the neighbouring ones
+pub fn step_constant_q(bins_per_octave: Option<u32>, direction: i32) -> Option<u32> {
+    match bins_per_octave {
+        None if direction > 0 => Some(CONSTANT_Q_RESOLUTIONS[0]),
+        None => None,
+        Some(current) if direction > 0 => CONSTANT_Q_RESOLUTIONS
+            .iter()
+            .find(|b| **b > current)
+            .copied()
+            .or(Some(current)),
+        Some(current) => CONSTANT_Q_RESOLUTIONS
+            .iter()
+            .rev()
+            .find(|b| **b < current)
+            .copied(),
+    }
+}
+
+pub fn step_display_mode(display_mode: &str, direction: i32) -> &'static str {
+    let names = DisplayStrategyFactory::NAMES;
+    let idx = names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(display_mode))
+        .unwrap_or(0) as i32;
+    names[(idx + direction).rem_euclid(names.len() as i32) as usize]
+}
+
+pub fn step_marker(marker: Marker, direction: i32) -> Marker {
+    let markers = Marker::ALL;
+    let idx = markers.iter().position(|m| *m == marker).unwrap_or(0) as i32;
+    markers[(idx + direction).rem_euclid(markers.len() as i32) as usize]
+}
+
+// moves by a third of an octave, so the step feels the same across the whole range
+pub fn step_freq(freq: f64, direction: i32) -> f64 {
+    (freq.max(10.) * 2f64.powf(f64::from(direction) / 3.)).round()
+}
+
+pub fn step_fps(fps: u8, direction: i32) -> u8 {
+    (i32::from(fps) + 5 * direction).clamp(1, 240) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_wrap_around_and_drop_their_parameter() {
+        let last = WindowType::ALL.len() - 1;
+        assert_eq!(step_window(&WindowType::ALL[0], 1), WindowType::ALL[1]);
+        assert_eq!(step_window(&WindowType::ALL[0], -1), WindowType::ALL[last]);
+        assert_eq!(step_window(&WindowType::ALL[last], 1), WindowType::ALL[0]);
+
+        let kaiser = WindowType::ALL
+            .iter()
+            .position(|w| w.name() == "kaiser")
+            .unwrap();
+        assert_eq!(
+            step_window(&WindowType::Kaiser(12.), 0),
+            WindowType::ALL[kaiser]
+        );
+    }
+
+    #[test]
+    fn fft_sizes_step_in_powers_of_two_within_limits() {
+        assert_eq!(step_fft_size(1024, 1), 2048);
+        assert_eq!(step_fft_size(1024, -1), 512);
+        // sizes from the command line are rounded up on the way
+        assert_eq!(step_fft_size(1000, 1), 2048);
+        assert_eq!(step_fft_size(1000, -1), 512);
+        assert_eq!(step_fft_size(MIN_FFT_SIZE, -1), MIN_FFT_SIZE);
+        assert_eq!(step_fft_size(MAX_FFT_SIZE, 1), MAX_FFT_SIZE);
+    }
+
+    #[test]
+    fn sample_rates_step_through_the_common_ones() {
+        assert_eq!(step_sample_rate(44100, 1), 48000);
+        assert_eq!(step_sample_rate(44100, -1), 32000);
+        assert_eq!(step_sample_rate(45000, -1), 44100);
+        assert_eq!(step_sample_rate(8000, -1), 8000);
+        assert_eq!(step_sample_rate(192000, 1), 192000);
+    }
+
+    #[test]
+    fn smoothing_steps_without_drifting() {
+        let mut smoothing = 0.7;
+        for _ in 0..3 {
+            smoothing = step_smoothing(smoothing, 1);
+        }
+        assert_eq!(smoothing, 0.85);
+        assert_eq!(step_smoothing(0.97, 1), 0.99);
+        assert_eq!(step_smoothing(0.02, -1), 0.);
+    }
+
+    #[test]
+    fn zero_padding_stays_within_what_the_flag_accepts() {
+        assert_eq!(step_zero_pad(1, 1), 2);
+        assert_eq!(step_zero_pad(4, -1), 2);
+        assert_eq!(step_zero_pad(3, 1), 8);
+        assert_eq!(step_zero_pad(1, -1), 1);
+        assert_eq!(step_zero_pad(16, 1), 16);
+    }
+
+    #[test]
+    fn constant_q_steps_from_the_plain_fft() {
+        assert_eq!(step_constant_q(None, 1), Some(12));
+        assert_eq!(step_constant_q(None, -1), None);
+        assert_eq!(step_constant_q(Some(12), -1), None);
+        assert_eq!(step_constant_q(Some(24), 1), Some(36));
+        assert_eq!(step_constant_q(Some(96), 1), Some(96));
+        // a resolution given on the command line steps to its neighbours
+        assert_eq!(step_constant_q(Some(30), 1), Some(36));
+        assert_eq!(step_constant_q(Some(30), -1), Some(24));
+    }
+
+    #[test]
+    fn display_modes_and_markers_cycle() {
+        assert_eq!(step_display_mode("discrete", 1), "POINT");
+        assert_eq!(step_display_mode("DISCRETE", -1), "AREA");
+        assert_eq!(step_display_mode("unknown", 1), "POINT");
+        assert_eq!(
+            step_marker(Marker::ALL[0], -1),
+            Marker::ALL[Marker::ALL.len() - 1]
+        );
+        assert_eq!(step_marker(Marker::ALL[0], 1), Marker::ALL[1]);
+    }
+
+    #[test]
+    fn frequencies_step_by_a_third_octave() {
+        assert_eq!(step_freq(1000., 1), 1260.);
+        assert_eq!(step_freq(1000., -1), 794.);
+        assert_eq!(step_freq(1000., 3), 2000.);
+        // 0 Hz steps up from 10 Hz
+        assert_eq!(step_freq(0., 1), 13.);
+    }
+
+    #[test]
+    fn fps_steps_by_five_within_limits() {
+        assert_eq!(step_fps(60, 1), 65);
+        assert_eq!(step_fps(60, -1), 55);
+        assert_eq!(step_fps(3, -1), 1);
+        assert_eq!(step_fps(238, 1), 240);
+    }
+
+    #[test]
+    fn every_setting_has_a_label() {
+        let labels: Vec<_> = Setting::ALL.iter().map(Setting::label).collect();
+        assert_eq!(labels.len(), 11);
+        for (i, label) in labels.iter().enumerate() {
+            assert!(!labels[..i].contains(label), "{label} twice");
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// how many snapshots are kept, storing another one drops the oldest
+pub const MAX_SNAPSHOTS: usize = 4;
+
+// a stored spectrum in dB, together with the bin spacing it was taken at so it can still be
+// compared after the FFT size or sample rate changed
+#[derive(Clone)]
+pub struct Snapshot {
+    pub bins: Vec<f64>,
+    pub freq_step: f64,
+}
+
+impl Snapshot {
+    // the level of the bin closest to `freq`
+    pub fn level_at(&self, freq: f64) -> Option<f64> {
+        let idx = (freq / self.freq_step).round() as usize;
+        self.bins.get(idx).copied()
+    }
+
+    // live minus snapshot in dB, for every live bin the snapshot covers
+    pub fn difference(&self, bins: &[f64], freq_step: f64) -> Vec<f64> {
+        bins.iter()
+            .enumerate()
+            .map_while(|(i, db)| Some(db - self.level_at(freq_step * i as f64)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(bins: &[f64], freq_step: f64) -> Snapshot {
+        Snapshot {
+            bins: bins.to_vec(),
+            freq_step,
+        }
+    }
+
+    #[test]
+    fn levels_come_from_the_nearest_bin() {
+        let snapshot = snapshot(&[-10., -20., -30.], 100.);
+        assert_eq!(snapshot.level_at(0.), Some(-10.));
+        assert_eq!(snapshot.level_at(140.), Some(-20.));
+        assert_eq!(snapshot.level_at(160.), Some(-30.));
+        assert_eq!(snapshot.level_at(260.), None);
+    }
+
+    #[test]
+    fn difference_at_the_same_resolution() {
+        let reference = snapshot(&[-10., -20., -30.], 100.);
+        assert_eq!(
+            reference.difference(&[-10., -14., -36.], 100.),
+            [0., 6., -6.]
+        );
+    }
+
+    #[test]
+    fn difference_after_the_resolution_changed() {
+        // the live spectrum has twice as many bins, each compared to the nearest stored one
+        let reference = snapshot(&[-10., -20., -30.], 100.);
+        assert_eq!(
+            reference.difference(&[-10., -10., -10., -10., -10.], 50.),
+            [0., 10., 10., 20., 20.]
+        );
+        // and live bins past the end of the snapshot are left out
+        assert_eq!(reference.difference(&[-10.; 5], 100.), [0., 10., 20.]);
+    }
+}
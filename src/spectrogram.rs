@@ -0,0 +1,364 @@
+use crate::{analyze::Analysis, error::Result};
+
+use std::{fs::File, io::BufWriter, ops::Range, path::Path};
+
+const PLOT_W: usize = 800;
+const PLOT_H: usize = 400;
+const LEFT: usize = 60;
+const TOP: usize = 24;
+const BOTTOM: usize = 40;
+const RIGHT: usize = 90;
+const WIDTH: usize = LEFT + PLOT_W + RIGHT;
+const HEIGHT: usize = TOP + PLOT_H + BOTTOM;
+
+// how many dB below the loudest bin the colormap reaches before clipping to black
+const DYNAMIC_RANGE: f64 = 100.;
+
+const BACKGROUND: [u8; 3] = [24, 24, 24];
+const FOREGROUND: [u8; 3] = [220, 220, 220];
+
+// rough approximation of the "inferno" colormap
+const COLORMAP: [(f64, [u8; 3]); 5] = [
+    (0.0, [0, 0, 4]),
+    (0.25, [87, 16, 110]),
+    (0.5, [188, 55, 84]),
+    (0.75, [249, 142, 9]),
+    (1.0, [252, 255, 164]),
+];
+
+// the loudest level of the frames and bins that fall into every pixel of the plot, collected
+// frame by frame so that a long file takes no more memory than a short one
+pub struct Spectrogram {
+    frame_count: usize,
+    bin_count: usize,
+    // PLOT_H levels per column, from the lowest bins up
+    levels: Vec<f64>,
+}
+
+impl Spectrogram {
+    pub fn new(frame_count: usize, bin_count: usize) -> Self {
+        Spectrogram {
+            frame_count,
+            bin_count,
+            levels: vec![f64::MIN; PLOT_W * PLOT_H],
+        }
+    }
+
+    // a frame or bin is spread over several pixels when there are fewer of them than pixels
+    pub fn push(&mut self, frame: usize, bins: &[f64]) {
+        let columns = pixels_of(frame, self.frame_count, PLOT_W);
+        for (bin, db) in bins.iter().take(self.bin_count).enumerate() {
+            for row in pixels_of(bin, self.bin_count, PLOT_H) {
+                for px in columns.clone() {
+                    let level = &mut self.levels[px * PLOT_H + row];
+                    *level = level.max(*db);
+                }
+            }
+        }
+    }
+
+    fn level(&self, px: usize, row: usize) -> f64 {
+        self.levels[px * PLOT_H + row]
+    }
+}
+
+// the pixels out of `pixels` that item `idx` of `count` falls into
+fn pixels_of(idx: usize, count: usize, pixels: usize) -> Range<usize> {
+    let start = idx * pixels / count;
+    start..((idx + 1) * pixels / count).max(start + 1)
+}
+
+struct Image {
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    fn new() -> Self {
+        Image {
+            pixels: BACKGROUND.repeat(WIDTH * HEIGHT),
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < WIDTH && y < HEIGHT {
+            let idx = (y * WIDTH + x) * 3;
+            self.pixels[idx..idx + 3].copy_from_slice(&color);
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, w: usize, h: usize, color: [u8; 3]) {
+        for yy in y..y + h {
+            for xx in x..x + w {
+                self.set(xx, yy, color);
+            }
+        }
+    }
+
+    // draws text with a tiny built-in bitmap font, each glyph is 3x5 scaled up twice
+    fn text(&mut self, x: usize, y: usize, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) != 0 {
+                        self.fill(x + i * 8 + col * 2, y + row * 2, 2, 2, FOREGROUND);
+                    }
+                }
+            }
+        }
+    }
+
+    fn text_right(&mut self, right: usize, y: usize, text: &str) {
+        let width = text.chars().count() * 8;
+        self.text(right.saturating_sub(width), y, text);
+    }
+
+    fn text_centered(&mut self, center: usize, y: usize, text: &str) {
+        let width = text.chars().count() * 8;
+        self.text(center.saturating_sub(width / 2), y, text);
+    }
+}
+
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        'k' => [0b100, 0b101, 0b110, 0b101, 0b101],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'z' => [0b000, 0b111, 0b010, 0b100, 0b111],
+        's' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'd' => [0b001, 0b001, 0b111, 0b101, 0b111],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        _ => [0; 5],
+    }
+}
+
+fn colormap(normal_val: f64) -> [u8; 3] {
+    let v = normal_val.clamp(0., 1.);
+    let upper = COLORMAP
+        .iter()
+        .position(|(stop, _)| *stop >= v)
+        .unwrap_or(0)
+        .max(1);
+    let (lo, lo_color) = COLORMAP[upper - 1];
+    let (hi, hi_color) = COLORMAP[upper];
+    let t = (v - lo) / (hi - lo);
+
+    let mut color = [0u8; 3];
+    for c in 0..3 {
+        color[c] =
+            (f64::from(lo_color[c]) + (f64::from(hi_color[c]) - f64::from(lo_color[c])) * t) as u8;
+    }
+    color
+}
+
+// picks a 1/2/5 * 10^n step that splits the range into roughly `ticks` pieces
+fn nice_step(range: f64, ticks: f64) -> f64 {
+    let raw = range / ticks;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    [1., 2., 5., 10.]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= raw)
+        .unwrap_or(10. * magnitude)
+}
+
+// the multiples of a nice step from 0 up to `range` together with the step, and none for an
+// empty range such as the frequency axis of a single bin
+fn ticks(range: f64, count: f64) -> (f64, Vec<f64>) {
+    let step = nice_step(range, count);
+    if !(range > 0. && step > 0.) {
+        return (step, vec![]);
+    }
+    // a range that is a multiple of the step keeps its last tick despite rounding
+    let last = (range / step + 1e-9).floor() as usize;
+    (step, (0..=last).map(|i| i as f64 * step).collect())
+}
+
+fn format_tick(value: f64, step: f64) -> String {
+    let decimals = if step.fract() == 0. {
+        0
+    } else {
+        (-step.log10().floor()) as usize
+    };
+    format!("{value:.decimals$}")
+}
+
+fn format_freq(freq: f64, step: f64) -> String {
+    if freq >= 1000. {
+        format!("{}k", format_tick(freq / 1000., step / 1000.))
+    } else {
+        format_tick(freq, step)
+    }
+}
+
+pub fn write_png(analysis: &Analysis, path: &Path) -> Result<()> {
+    let mut image = Image::new();
+
+    let bin_count = analysis.average.len();
+    let top_db = (analysis.max_hold.iter().cloned().fold(f64::MIN, f64::max) / 10.).ceil() * 10.;
+    let bottom_db = top_db - DYNAMIC_RANGE;
+
+    for px in 0..PLOT_W {
+        for py in 0..PLOT_H {
+            let db = analysis.spectrogram.level(px, PLOT_H - 1 - py);
+            image.set(
+                LEFT + px,
+                TOP + py,
+                colormap((db - bottom_db) / DYNAMIC_RANGE),
+            );
+        }
+    }
+
+    // frequency axis
+    let nyquist = analysis.freq_step * bin_count.saturating_sub(1) as f64;
+    let (freq_step, freqs) = ticks(nyquist, 5.);
+    for freq in freqs {
+        let y = TOP + PLOT_H - 1 - (freq / nyquist * (PLOT_H - 1) as f64) as usize;
+        image.fill(LEFT - 5, y, 5, 1, FOREGROUND);
+        image.text_right(LEFT - 8, y.saturating_sub(5), &format_freq(freq, freq_step));
+    }
+    image.text_right(LEFT - 8, 4, "Hz");
+
+    // time axis
+    let total_secs = analysis.hop_secs * analysis.frame_count as f64;
+    let (time_step, times) = ticks(total_secs, 6.);
+    for time in times {
+        let x = LEFT + (time / total_secs * (PLOT_W - 1) as f64) as usize;
+        image.fill(x, TOP + PLOT_H, 1, 5, FOREGROUND);
+        image.text_centered(x, TOP + PLOT_H + 8, &format_tick(time, time_step));
+    }
+    image.text_right(LEFT + PLOT_W, TOP + PLOT_H + 24, "s");
+
+    // colormap legend
+    let legend_x = LEFT + PLOT_W + 16;
+    for py in 0..PLOT_H {
+        let color = colormap(1. - py as f64 / (PLOT_H - 1) as f64);
+        image.fill(legend_x, TOP + py, 16, 1, color);
+    }
+    let (db_step, offsets) = ticks(DYNAMIC_RANGE, 5.);
+    for db in offsets.into_iter().map(|offset| bottom_db + offset) {
+        let y =
+            TOP + PLOT_H - 1 - ((db - bottom_db) / DYNAMIC_RANGE * (PLOT_H - 1) as f64) as usize;
+        image.fill(legend_x + 16, y, 4, 1, FOREGROUND);
+        image.text(
+            legend_x + 24,
+            y.saturating_sub(5),
+            &format_tick(db, db_step),
+        );
+    }
+    image.text(legend_x, 4, "dB");
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_are_spread_over_pixels_or_share_them() {
+        assert_eq!(pixels_of(0, 2, PLOT_W), 0..PLOT_W / 2);
+        assert_eq!(pixels_of(1, 2, PLOT_W), PLOT_W / 2..PLOT_W);
+        assert_eq!(pixels_of(0, 3, 10), 0..3);
+        assert_eq!(pixels_of(2, 3, 10), 6..10);
+        // more items than pixels
+        assert_eq!(pixels_of(0, 2 * PLOT_W, PLOT_W), 0..1);
+        assert_eq!(pixels_of(1, 2 * PLOT_W, PLOT_W), 0..1);
+        assert_eq!(
+            pixels_of(2 * PLOT_W - 1, 2 * PLOT_W, PLOT_W),
+            PLOT_W - 1..PLOT_W
+        );
+    }
+
+    #[test]
+    fn pixels_keep_the_loudest_level() {
+        // two frames per column and two bins per row
+        let mut spectrogram = Spectrogram::new(2 * PLOT_W, 2 * PLOT_H);
+        let mut bins = vec![-80.; 2 * PLOT_H];
+        bins[1] = -10.;
+        spectrogram.push(0, &bins);
+        bins[1] = -80.;
+        bins[2] = -30.;
+        spectrogram.push(1, &bins);
+        spectrogram.push(2, &bins);
+
+        assert_eq!(spectrogram.level(0, 0), -10.);
+        assert_eq!(spectrogram.level(0, 1), -30.);
+        assert_eq!(spectrogram.level(0, 2), -80.);
+        assert_eq!(spectrogram.level(1, 1), -30.);
+        assert_eq!(spectrogram.level(1, 0), -80.);
+        // nothing was pushed there yet
+        assert_eq!(spectrogram.level(2, 0), f64::MIN);
+    }
+
+    #[test]
+    fn a_single_frame_fills_the_plot() {
+        let mut spectrogram = Spectrogram::new(1, 3);
+        spectrogram.push(0, &[-10., -20., -30.]);
+        for px in [0, PLOT_W / 2, PLOT_W - 1] {
+            assert_eq!(spectrogram.level(px, 0), -10.);
+            assert_eq!(spectrogram.level(px, PLOT_H / 2), -20.);
+            assert_eq!(spectrogram.level(px, PLOT_H - 1), -30.);
+        }
+    }
+
+    #[test]
+    fn ticks_at_nice_steps() {
+        let (step, freqs) = ticks(22050., 5.);
+        assert_eq!(step, 5000.);
+        assert_eq!(freqs, [0., 5000., 10000., 15000., 20000.]);
+
+        let (step, times) = ticks(0.3, 6.);
+        assert_eq!(step, 0.05);
+        assert_eq!(times.len(), 7);
+        assert_eq!(format_tick(times[6], step), "0.30");
+
+        for range in [0., -1., f64::NAN] {
+            assert!(ticks(range, 5.).1.is_empty(), "{range}");
+        }
+    }
+
+    #[test]
+    fn tick_labels() {
+        assert_eq!(format_tick(-20., 20.), "-20");
+        assert_eq!(format_tick(1.5, 0.5), "1.5");
+        assert_eq!(format_freq(500., 500.), "500");
+        assert_eq!(format_freq(15000., 5000.), "15k");
+        assert_eq!(format_freq(2500., 500.), "2.5k");
+    }
+
+    #[test]
+    fn a_single_bin_is_written() {
+        let mut spectrogram = Spectrogram::new(1, 1);
+        spectrogram.push(0, &[-20.]);
+        let analysis = Analysis {
+            frame_count: 1,
+            spectrogram,
+            average: vec![-20.],
+            max_hold: vec![-20.],
+            freq_step: 43.,
+            hop_secs: 0.01,
+        };
+        let path = std::env::temp_dir().join("audiolyzer-single-bin.png");
+        write_png(&analysis, &path).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+use crate::{
+    config::Config,
+    error::{Error, Result},
+};
+use audiolyzer::display::{ColorDepth, Coloring, Gradient};
+
+use std::str::FromStr;
+
+use ratatui::style::Color;
+use toml_edit::{Item, Value};
+
+// every color the UI uses, so a theme can change all of them at once
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    pub spectrum: Coloring,
+    // the difference trace and other plain drawing
+    pub foreground: Color,
+    // recording, errors, the device lost banner and failing mask bins
+    pub warning: Color,
+    // triggers and freezing
+    pub notice: Color,
+    // passing masks
+    pub ok: Color,
+    // popup backgrounds and guide lines
+    pub muted: Color,
+    // selected rows and key names
+    pub highlight: Color,
+    pub snapshots: [Color; 4],
+    pub reference: Color,
+    pub mask: Color,
+    // from quiet to loud in the waterfall
+    pub waterfall: Gradient,
+}
+
+impl Theme {
+    fn builtin() -> Vec<Theme> {
+        let default = Theme {
+            name: "default".into(),
+            spectrum: Coloring::Solid(Color::White),
+            foreground: Color::White,
+            warning: Color::Red,
+            notice: Color::Yellow,
+            ok: Color::Green,
+            muted: Color::DarkGray,
+            highlight: Color::LightYellow,
+            snapshots: [Color::Cyan, Color::Magenta, Color::Yellow, Color::Green],
+            reference: Color::LightBlue,
+            mask: Color::LightRed,
+            // roughly the "inferno" colormap of the spectrogram images
+            waterfall: Gradient(vec![
+                (0, 0, 4),
+                (87, 16, 110),
+                (188, 55, 84),
+                (249, 142, 9),
+                (252, 255, 164),
+            ]),
+        };
+        let heat_gradient = Gradient(vec![
+            (0, 0, 128),
+            (0, 160, 255),
+            (255, 255, 0),
+            (255, 64, 0),
+            (255, 255, 255),
+        ]);
+        let heat = Theme {
+            name: "heat".into(),
+            spectrum: Coloring::ByLevel(heat_gradient.clone()),
+            waterfall: heat_gradient,
+            ..default.clone()
+        };
+        let rainbow = Theme {
+            name: "rainbow".into(),
+            spectrum: Coloring::ByFrequency(Gradient(vec![
+                (255, 0, 0),
+                (255, 165, 0),
+                (255, 255, 0),
+                (0, 255, 0),
+                (0, 128, 255),
+                (160, 0, 255),
+            ])),
+            ..default.clone()
+        };
+        // black and white only, for low vision and monochrome terminals
+        let mono = Theme {
+            name: "mono".into(),
+            spectrum: Coloring::Solid(Color::White),
+            foreground: Color::White,
+            warning: Color::White,
+            notice: Color::White,
+            ok: Color::White,
+            muted: Color::DarkGray,
+            highlight: Color::White,
+            // shades of gray from light to dark, a 16 color terminal only has two of them
+            // below white so the last two snapshots share one there
+            snapshots: [
+                Color::Rgb(255, 255, 255),
+                Color::Rgb(200, 200, 200),
+                Color::Rgb(150, 150, 150),
+                Color::Rgb(110, 110, 110),
+            ],
+            reference: Color::White,
+            mask: Color::White,
+            waterfall: Gradient(vec![(0, 0, 0), (255, 255, 255)]),
+        };
+        vec![default, heat, rainbow, mono]
+    }
+
+    // colors such as "#ff8000" are replaced by the closest one the terminal can show
+    fn adapt(mut self, depth: ColorDepth) -> Theme {
+        for color in [
+            &mut self.foreground,
+            &mut self.warning,
+            &mut self.notice,
+            &mut self.ok,
+            &mut self.muted,
+            &mut self.highlight,
+            &mut self.reference,
+            &mut self.mask,
+        ]
+        .into_iter()
+        .chain(self.snapshots.iter_mut())
+        {
+            *color = depth.adapt(*color);
+        }
+        self
+    }
+}
+
+// the built-in themes followed by the [themes.<name>] tables of the config, which start
+// from the theme named by `base` (or the default one) and replace the colors they list
+pub fn load(config: &Config, depth: ColorDepth) -> Result<Vec<Theme>> {
+    let mut themes = Theme::builtin();
+
+    for (name, item) in config.section("themes") {
+        let table = item
+            .as_table_like()
+            .ok_or_else(|| Error::Config(format!("themes.{name} must be a table")))?;
+        let base = table
+            .get("base")
+            .and_then(Item::as_str)
+            .unwrap_or("default");
+        let mut theme = themes
+            .iter()
+            .find(|t| t.name == base)
+            .cloned()
+            .ok_or_else(|| Error::Config(format!("themes.{name}: unknown base theme {base}")))?;
+        theme.name = name.into();
+
+        for (key, item) in table.iter() {
+            let invalid =
+                |message: String| Error::Config(format!("themes.{name}.{key}: {message}"));
+            let value = item
+                .as_value()
+                .ok_or_else(|| invalid("expected a value".into()))?;
+            let slot = match key {
+                "base" | "gradient" | "gradient_by" => continue,
+                "waterfall" => {
+                    theme.waterfall = parse_gradient(value).map_err(invalid)?;
+                    continue;
+                }
+                "spectrum" => {
+                    theme.spectrum = Coloring::Solid(parse_color(value).map_err(invalid)?);
+                    continue;
+                }
+                "snapshots" => {
+                    let colors = parse_colors(value).map_err(invalid)?;
+                    for (slot, color) in theme.snapshots.iter_mut().zip(colors.iter().cycle()) {
+                        *slot = *color;
+                    }
+                    continue;
+                }
+                "foreground" => &mut theme.foreground,
+                "warning" => &mut theme.warning,
+                "notice" => &mut theme.notice,
+                "ok" => &mut theme.ok,
+                "muted" => &mut theme.muted,
+                "highlight" => &mut theme.highlight,
+                "reference" => &mut theme.reference,
+                "mask" => &mut theme.mask,
+                _ => return Err(invalid("unknown key".into())),
+            };
+            *slot = parse_color(value).map_err(invalid)?;
+        }
+
+        // a gradient colors the spectrum by level unless gradient_by says otherwise
+        if let Some(gradient) = table.get("gradient").and_then(Item::as_value) {
+            let invalid =
+                |message: String| Error::Config(format!("themes.{name}.gradient: {message}"));
+            let gradient = parse_gradient(gradient).map_err(invalid)?;
+            theme.spectrum = match table.get("gradient_by").and_then(Item::as_str) {
+                None | Some("level") => Coloring::ByLevel(gradient),
+                Some("frequency") => Coloring::ByFrequency(gradient),
+                Some(other) => {
+                    return Err(Error::Config(format!(
+                        "themes.{name}.gradient_by: expected level or frequency, got {other}"
+                    )))
+                }
+            };
+        }
+
+        match themes.iter_mut().find(|t| t.name == theme.name) {
+            Some(existing) => *existing = theme,
+            None => themes.push(theme),
+        }
+    }
+
+    Ok(themes.into_iter().map(|theme| theme.adapt(depth)).collect())
+}
+
+// names such as "lightblue", "#rrggbb" or a 256 color index
+fn parse_color(value: &Value) -> std::result::Result<Color, String> {
+    let name = value.as_str().ok_or("expected a color name or #rrggbb")?;
+    Color::from_str(name).map_err(|_| format!("unknown color {name}"))
+}
+
+fn parse_colors(value: &Value) -> std::result::Result<Vec<Color>, String> {
+    let array = value.as_array().ok_or("expected a list of colors")?;
+    if array.is_empty() {
+        return Err("expected at least one color".into());
+    }
+    array.iter().map(parse_color).collect()
+}
+
+fn parse_gradient(value: &Value) -> std::result::Result<Gradient, String> {
+    parse_colors(value)?
+        .into_iter()
+        .map(|color| rgb(color).ok_or_else(|| "stops must be #rrggbb colors".to_string()))
+        .collect::<std::result::Result<_, _>>()
+        .map(Gradient)
+}
+
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn load_toml(toml: &str, depth: ColorDepth) -> Result<Vec<Theme>> {
+        let config = Config::parse(PathBuf::from("config.toml"), None, toml)?;
+        load(&config, depth)
+    }
+
+    fn find<'a>(themes: &'a [Theme], name: &str) -> &'a Theme {
+        themes.iter().find(|t| t.name == name).unwrap()
+    }
+
+    #[test]
+    fn builtin_themes_come_first() {
+        let themes = load_toml("", ColorDepth::TrueColor).unwrap();
+        let names: Vec<&str> = themes.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["default", "heat", "rainbow", "mono"]);
+    }
+
+    #[test]
+    fn mono_snapshots_can_be_told_apart() {
+        for depth in [ColorDepth::TrueColor, ColorDepth::Ansi256] {
+            let themes = load_toml("", depth).unwrap();
+            let snapshots = find(&themes, "mono").snapshots;
+            for (i, color) in snapshots.iter().enumerate() {
+                assert!(
+                    !snapshots[i + 1..].contains(color),
+                    "{depth:?}: {snapshots:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn custom_themes_start_from_their_base() {
+        let toml = r##"
+            [themes.night]
+            base = "heat"
+            warning = "#ff8000"
+            snapshots = ["red", "blue"]
+        "##;
+        let themes = load_toml(toml, ColorDepth::TrueColor).unwrap();
+        assert_eq!(themes.len(), 5);
+        let night = find(&themes, "night");
+        assert_eq!(night.warning, Color::Rgb(255, 128, 0));
+        // the list repeats to fill all four
+        assert_eq!(
+            night.snapshots,
+            [Color::Red, Color::Blue, Color::Red, Color::Blue]
+        );
+        // everything else is the heat theme's
+        assert!(matches!(night.spectrum, Coloring::ByLevel(_)));
+        assert_eq!(night.ok, Color::Green);
+    }
+
+    #[test]
+    fn themes_are_reduced_to_the_color_depth() {
+        let toml = "[themes.default]\nwarning = \"#fa0a0a\"\n";
+        let themes = load_toml(toml, ColorDepth::Ansi16).unwrap();
+        // replaced in place rather than added
+        assert_eq!(themes.len(), 4);
+        assert_eq!(themes[0].warning, Color::LightRed);
+    }
+
+    #[test]
+    fn gradients_color_the_spectrum() {
+        let toml = r##"
+            [themes.level]
+            gradient = ["#000000", "#ffffff"]
+
+            [themes.frequency]
+            gradient = ["#000000", "#ffffff"]
+            gradient_by = "frequency"
+        "##;
+        let themes = load_toml(toml, ColorDepth::TrueColor).unwrap();
+        match &find(&themes, "level").spectrum {
+            Coloring::ByLevel(gradient) => assert_eq!(gradient.0, [(0, 0, 0), (255, 255, 255)]),
+            other => panic!("{other:?}"),
+        }
+        assert!(matches!(
+            find(&themes, "frequency").spectrum,
+            Coloring::ByFrequency(_)
+        ));
+    }
+
+    #[test]
+    fn invalid_themes_name_the_offending_key() {
+        let error = |toml: &str| match load_toml(toml, ColorDepth::TrueColor) {
+            Err(Error::Config(message)) => message,
+            other => panic!("{toml:?}: {other:?}"),
+        };
+        assert_eq!(error("[themes]\na = 3"), "themes.a must be a table");
+        assert_eq!(
+            error("[themes.a]\nbase = \"neon\""),
+            "themes.a: unknown base theme neon"
+        );
+        assert_eq!(
+            error("[themes.a]\nok = \"grean\""),
+            "themes.a.ok: unknown color grean"
+        );
+        assert_eq!(
+            error("[themes.a]\nborder = \"red\""),
+            "themes.a.border: unknown key"
+        );
+        assert_eq!(
+            error("[themes.a]\nsnapshots = []"),
+            "themes.a.snapshots: expected at least one color"
+        );
+        assert_eq!(
+            error("[themes.a]\nwaterfall = [\"red\"]"),
+            "themes.a.waterfall: stops must be #rrggbb colors"
+        );
+        assert_eq!(
+            error("[themes.a]\ngradient = [\"#000000\"]\ngradient_by = \"time\""),
+            "themes.a.gradient_by: expected level or frequency, got time"
+        );
+    }
+}
@@ -1,69 +1,716 @@
-use crate::{app::App, display::DisplayStrategyFactory};
+use crate::{
+    app::App,
+    capture::DeviceDetails,
+    curve::{Curve, CurveKind},
+    inputs::keymap::Action,
+    layout::{Pane, View},
+    settings::Setting,
+    snapshot::Snapshot,
+    theme::Theme,
+};
+use audiolyzer::{
+    display::{Coloring, DisplayStrategyFactory, Palette},
+    fft::Transform,
+    plot::{Painter, Plot},
+};
 
 use cpal::traits::DeviceTrait;
 
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style},
-    widgets::{canvas::Canvas, Block, Borders, Clear, Paragraph},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
-pub struct UI;
-
 pub fn ui(f: &mut Frame, app: &App) {
-    let canvas = Canvas::default()
-        .block(
-            Block::default()
-                .title(format!(
-                    "audiolyzer - Window: {:?} - FPS: {:?} - Input Device: {:?}",
-                    app.fft_engine.get_window(),
-                    app.args.fps,
-                    app.in_devices[app.in_devices_idx].name().unwrap()
+    let theme = app.theme();
+    let size = f.area();
+
+    let areas = app.presets[app.preset_idx].areas(size);
+    for (idx, (pane, area)) in app.panes.iter().zip(areas).enumerate() {
+        // the first pane carries what is shown for the whole app
+        let mut title = if idx == 0 {
+            header(app)
+        } else {
+            vec![Span::raw(format!(" {} ", pane.view.name()))]
+        };
+        let mut block = Block::default().borders(Borders::ALL);
+        if app.panes.len() > 1 && idx == app.focus {
+            block = block.border_style(Style::default().fg(theme.highlight));
+        }
+        if idx == 0 {
+            if let Some(status) = &app.status {
+                block = block.title_bottom(Line::styled(
+                    format!(" {status} "),
+                    Style::default().fg(theme.warning),
+                ));
+            }
+        }
+        if pane.view.has_freq_axis() {
+            let (low, high) = app.freq_view;
+            let mut view = format!(" {} - {} ", format_freq(low), format_freq(high));
+            if let Some(freq) = app.cursor_freq {
+                view = format!("{view}- cursor {} ", format_freq(freq));
+            }
+            block = block.title_bottom(Line::raw(view).right_aligned());
+        }
+
+        match pane.view {
+            View::Spectrum => render_spectrum(f, app, pane, area, block, title),
+            View::Waterfall => {
+                f.render_widget(block.title(Line::from(title)), area);
+                render_waterfall(f, app, area);
+            }
+            View::Meters => render_meters(f, app, area, block.title(Line::from(title))),
+            View::Scope => {
+                let duration =
+                    app.samples.len() as f64 * 1000. / f64::from(app.stream_info.sample_rate);
+                title.push(Span::raw(format!("- {duration:.1} ms ")));
+                render_scope(f, app, pane, area, block.title(Line::from(title)), duration);
+            }
+        }
+    }
+
+    match app.edit_in_device {
+        true => render_picker(f, app, size),
+        false => {
+            if app.device_lost {
+                let area = centered_rect(50, 20, size);
+                let banner = Paragraph::new(format!(
+                    "Device lost: {}\nwaiting for it to reconnect...",
+                    app.device_name
                 ))
-                .borders(Borders::ALL),
-        )
-        .x_bounds([app.args.min_freq.into(), app.args.max_freq.into()])
+                .alignment(Alignment::Center)
+                .block(Block::default().borders(Borders::ALL))
+                .style(Style::default().bg(theme.warning).fg(Color::Black));
+                f.render_widget(Clear, area);
+                f.render_widget(banner, area);
+            }
+        }
+    }
+
+    if app.show_perf {
+        render_perf(f, app, size);
+    }
+    if app.show_settings {
+        render_settings(f, app, size);
+    }
+    if app.show_help {
+        render_help(f, app, size);
+    }
+}
+
+// hosts in the top row, then the filter, the devices matching it and the details of the
+// selected one side by side
+fn render_picker(f: &mut Frame, app: &App, size: Rect) {
+    let theme = app.theme();
+    let area = centered_rect(70, 60, size);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ])
+        .split(area);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[2]);
+
+    let active_style = Style::default().bg(theme.highlight).fg(Color::Black);
+    let current_style = Style::default().fg(theme.highlight);
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Block::default().style(Style::default().bg(theme.muted)),
+        area,
+    );
+
+    let mut hosts = vec![Span::raw("Host (Tab): ")];
+    app.hosts.iter().enumerate().for_each(|(i, id)| {
+        let style = if app.edit_host && i == app.hosts_idx {
+            active_style
+        } else if *id == app.host.id() {
+            current_style
+        } else {
+            Style::default()
+        };
+        hosts.push(Span::styled(format!(" {} ", id.name()), style));
+    });
+    f.render_widget(Paragraph::new(Line::from(hosts)), rows[0]);
+
+    let filter = if app.device_filter.is_empty() && !app.edit_host {
+        Span::styled("type to filter", Style::default().fg(theme.highlight))
+    } else {
+        Span::raw(app.device_filter.as_str())
+    };
+    f.render_widget(
+        Paragraph::new(Line::from(vec![Span::raw("Filter: "), filter])),
+        rows[1],
+    );
+
+    let visible = app.visible_devices();
+    let items: Vec<ListItem> = visible
+        .iter()
+        .map(|i| {
+            let name = app.in_devices[*i]
+                .name()
+                .unwrap_or_else(|_| "unknown".into());
+            let item = ListItem::new(name.clone());
+            if name == app.device_name {
+                item.style(current_style)
+            } else {
+                item
+            }
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            " Devices ({} of {}) ",
+            visible.len(),
+            app.in_devices.len()
+        )))
+        .highlight_style(if app.edit_host {
+            Style::default()
+        } else {
+            active_style
+        });
+    let mut state =
+        ListState::default().with_selected(visible.iter().position(|i| *i == app.in_devices_idx));
+    f.render_stateful_widget(list, columns[0], &mut state);
+
+    let details = match &app.device_details {
+        Some((idx, details)) if visible.contains(idx) => device_details(details),
+        _ => vec![],
+    };
+    f.render_widget(
+        Paragraph::new(details).block(Block::default().borders(Borders::ALL).title(" Details ")),
+        columns[1],
+    );
+}
+
+fn device_details(details: &DeviceDetails) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    if let Some(config) = &details.default_config {
+        lines.push(Line::raw(format!(
+            "Default: {} ch, {} Hz, {}",
+            config.channels(),
+            config.sample_rate().0,
+            config.sample_format()
+        )));
+    }
+    match &details.configs {
+        Ok(configs) => {
+            lines.push(Line::raw("Supported:"));
+            for config in configs {
+                let (min, max) = (config.min_sample_rate().0, config.max_sample_rate().0);
+                let rates = if min == max {
+                    format!("{min} Hz")
+                } else {
+                    format!("{min} - {max} Hz")
+                };
+                lines.push(Line::raw(format!(
+                    "  {} ch, {rates}, {}",
+                    config.channels(),
+                    config.sample_format()
+                )));
+            }
+        }
+        Err(err) => lines.push(Line::raw(format!("Supported configs unavailable: {err}"))),
+    }
+    lines
+}
+
+// window, device, recording and trigger state, which apply to every pane
+fn header(app: &App) -> Vec<Span<'static>> {
+    let theme = app.theme();
+    // zero padding makes the bins finer than what the window can actually separate, so both
+    // are shown
+    let window = app.fft_engine.get_window();
+    let transform = match app.fft_engine.get_transform() {
+        Transform::Fft => format!(
+            " (main lobe {:.1} Hz, bins {:.1} Hz)",
+            app.fft_engine.get_main_lobe_bins() * f64::from(app.stream_info.sample_rate)
+                / f64::from(app.args.fft_size),
+            app.freq_step
+        ),
+        Transform::ConstantQ(bins_per_octave) => format!(" - Constant-Q: {bins_per_octave}/oct"),
+    };
+    let mut title = vec![Span::raw(format!(
+        "audiolyzer - Window: {window}{transform} - FPS: {:.0}/{} - Input Device: {:?} ({}, {})",
+        app.perf.fps(),
+        app.args.fps,
+        app.device_name,
+        app.stream_host().name(),
+        app.stream_info
+    ))];
+    if let Some(recording) = &app.recording {
+        let secs = recording.elapsed().as_secs();
+        title.push(Span::styled(
+            format!(
+                " ● REC {:02}:{:02} {} ",
+                secs / 60,
+                secs % 60,
+                recording.path.display()
+            ),
+            Style::default().fg(theme.warning),
+        ));
+    }
+
+    if app.pretrigger.is_pending() {
+        title.push(Span::styled(
+            " ● TRIGGERED ",
+            Style::default().fg(theme.notice),
+        ));
+    } else if let Some(path) = &app.last_dump {
+        title.push(Span::raw(format!(" - Saved: {} ", path.display())));
+    }
+
+    if app.frozen.is_some() {
+        title.push(Span::styled(
+            " ❚❚ FROZEN ",
+            Style::default().fg(theme.notice),
+        ));
+    }
+    title
+}
+
+fn render_spectrum(
+    f: &mut Frame,
+    app: &App,
+    pane: &Pane,
+    area: Rect,
+    block: Block,
+    mut title: Vec<Span>,
+) {
+    let theme = app.theme();
+    for (i, _) in app.snapshots.iter().enumerate() {
+        let marker = if i == app.reference_idx { "◆" } else { "■" };
+        title.push(Span::styled(
+            format!(" {marker}{}", i + 1),
+            Style::default().fg(theme.snapshots[i % theme.snapshots.len()]),
+        ));
+    }
+    if app.show_difference {
+        title.push(Span::raw(format!(
+            " - Difference vs {} (±{DIFFERENCE_RANGE_DB} dB) ",
+            app.reference_idx + 1
+        )));
+    }
+
+    let spectrum = app.display_spectrum();
+    let violations: Vec<usize> = app
+        .curves
+        .iter()
+        .flat_map(|c| c.violations(&spectrum.bins, spectrum.freq_step))
+        .collect();
+    for curve in &app.curves {
+        title.push(Span::styled(
+            format!(" ~{}", curve.name),
+            Style::default().fg(curve_color(curve, theme)),
+        ));
+    }
+    if app.curves.iter().any(|c| c.kind != CurveKind::Reference) {
+        title.push(if violations.is_empty() {
+            Span::styled(" MASK PASS ", Style::default().fg(theme.ok))
+        } else {
+            Span::styled(
+                format!(" MASK FAIL ({} bins) ", violations.len()),
+                Style::default().fg(theme.warning),
+            )
+        });
+    }
+
+    let (low, high) = app.freq_view;
+    let canvas = Plot::default()
+        .block(block.title(Line::from(title)))
+        .marker(pane.marker)
+        .x_bounds([low, high])
         .y_bounds([0.0, 1.0])
-        .paint(|ctx| {
-            let mut freq_data = app.fft_engine.get_bins();
-            freq_data.iter_mut().for_each(|x| *x = app.normalize_db(*x));
-            let s = DisplayStrategyFactory::get_display_strategy(&app.args.display_mode);
-            s.render(ctx, &freq_data, app.freq_step);
+        .paint(|painter| {
+            if let Some(reference) = app.reference().filter(|_| app.show_difference) {
+                // 0 dB sits in the middle, louder than the reference goes up
+                let difference: Vec<f64> = reference
+                    .difference(&spectrum.bins, spectrum.freq_step)
+                    .iter()
+                    .map(|db| (0.5 + db / (2. * DIFFERENCE_RANGE_DB)).clamp(0., 1.))
+                    .collect();
+                painter.line(low, 0.5, high, 0.5, theme.muted);
+                draw_trace(painter, &difference, spectrum.freq_step, theme.foreground);
+                return;
+            }
+
+            let freq_data: Vec<f64> = spectrum.bins.iter().map(|x| app.normalize_db(*x)).collect();
+            let s = DisplayStrategyFactory::get_display_strategy(&pane.display_mode);
+            s.render(painter, &freq_data, spectrum.freq_step, &app.palette());
+
+            // the failing bins are drawn again on top
+            for idx in &violations {
+                let freq = spectrum.freq_step * *idx as f64;
+                painter.line(freq, 0., freq, freq_data[*idx], theme.warning);
+            }
+            for curve in &app.curves {
+                draw_curve(painter, app, curve);
+            }
+
+            for (i, snapshot) in app.snapshots.iter().enumerate() {
+                let levels: Vec<f64> = snapshot.bins.iter().map(|x| app.normalize_db(*x)).collect();
+                draw_trace(
+                    painter,
+                    &levels,
+                    snapshot.freq_step,
+                    theme.snapshots[i % theme.snapshots.len()],
+                );
+            }
         });
+    f.render_widget(canvas, area);
+}
 
-    let size = f.area();
+// every cell shows two spectra with an upper half block, the newer one in the foreground
+fn render_waterfall(f: &mut Frame, app: &App, area: Rect) {
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    let gradient = &app.theme().waterfall;
+    let color = |row: Option<&Vec<f64>>, col: usize| match row {
+        Some(levels) => app.color_depth.color(gradient.at(levels[col])),
+        None => Color::Reset,
+    };
 
-    match app.edit_in_device {
-        true => {
-            let popup_block = Block::default()
-                .title("")
-                .borders(Borders::NONE)
-                .style(Style::default().bg(Color::DarkGray));
-            let area = centered_rect(60, 25, size);
-
-            let popup_chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints(Constraint::from_lengths(vec![1; app.in_devices.len()]))
-                .split(area);
-
-            let active_style = Style::default().bg(Color::LightYellow).fg(Color::Black);
-
-            f.render_widget(canvas, size);
-            f.render_widget(Clear, area);
-            f.render_widget(popup_block, area);
-            app.in_devices.iter().enumerate().for_each(|(i, d)| {
-                let mut text = Paragraph::new(d.name().unwrap().clone());
-                if i == app.in_devices_idx {
-                    text = text.style(active_style);
-                }
-                f.render_widget(text, popup_chunks[i]);
-            });
+    let rows: Vec<Vec<f64>> = app
+        .waterfall
+        .iter()
+        .take(usize::from(inner.height) * 2)
+        .map(|snapshot| column_levels(app, snapshot, inner.width))
+        .collect();
+    let buf = f.buffer_mut();
+    for y in 0..inner.height {
+        let (newer, older) = (
+            rows.get(2 * usize::from(y)),
+            rows.get(2 * usize::from(y) + 1),
+        );
+        if newer.is_none() {
+            break;
+        }
+        for x in 0..inner.width {
+            if let Some(cell) = buf.cell_mut((inner.x + x, inner.y + y)) {
+                cell.set_char('▀')
+                    .set_fg(color(newer, usize::from(x)))
+                    .set_bg(color(older, usize::from(x)));
+            }
         }
-        false => f.render_widget(canvas, size),
     }
 }
 
+// the loudest bin under every column of the view, normalized to [0, 1]
+fn column_levels(app: &App, snapshot: &Snapshot, width: u16) -> Vec<f64> {
+    let (low, high) = app.freq_view;
+    let span = (high - low) / f64::from(width);
+    let len = snapshot.bins.len();
+    (0..width)
+        .map(|col| {
+            let start = low + span * f64::from(col);
+            let first = (start / snapshot.freq_step).ceil() as usize;
+            let end = ((start + span) / snapshot.freq_step).ceil() as usize;
+            // zoomed in further than the bin spacing, the nearest bin covers the column
+            let (first, end) = if first < end {
+                (first, end)
+            } else {
+                let nearest = ((start + span / 2.) / snapshot.freq_step).round() as usize;
+                (nearest, nearest + 1)
+            };
+            snapshot.bins[first.min(len)..end.min(len)]
+                .iter()
+                .copied()
+                .reduce(f64::max)
+                .map_or(0., |db| app.normalize_db(db))
+        })
+        .collect()
+}
+
+// the meters show this many dB below full scale
+const METER_RANGE_DB: f64 = 60.;
+// peaks above this are shown as close to clipping
+const METER_WARNING_DBFS: f64 = -1.;
+
+fn render_meters(f: &mut Frame, app: &App, area: Rect, block: Block) {
+    let theme = app.theme();
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let samples = &app.samples;
+    let rms = (samples.iter().map(|s| f64::from(*s).powi(2)).sum::<f64>()
+        / samples.len().max(1) as f64)
+        .sqrt();
+    let peak = samples
+        .iter()
+        .fold(0f64, |peak, s| peak.max(f64::from(s.abs())));
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+    for (row, (label, level)) in [("RMS ", rms), ("Peak", peak)].into_iter().enumerate() {
+        let dbfs = 20. * level.max(1e-10).log10();
+        let color = if dbfs > METER_WARNING_DBFS {
+            theme.warning
+        } else {
+            theme.ok
+        };
+        let gauge = Gauge::default()
+            .ratio((1. + dbfs / METER_RANGE_DB).clamp(0., 1.))
+            .label(format!("{label} {dbfs:>6.1} dBFS"))
+            .gauge_style(Style::default().fg(color).bg(theme.muted));
+        f.render_widget(gauge, rows[row * 2]);
+    }
+}
+
+// the latest block with full scale at the top and bottom edge
+fn render_scope(f: &mut Frame, app: &App, pane: &Pane, area: Rect, block: Block, duration: f64) {
+    let theme = app.theme();
+    let levels: Vec<f64> = app
+        .samples
+        .iter()
+        .map(|s| ((f64::from(*s) + 1.) / 2.).clamp(0., 1.))
+        .collect();
+    let step = 1000. / f64::from(app.stream_info.sample_rate);
+    let palette = Palette {
+        coloring: Coloring::Solid(theme.foreground),
+        depth: app.color_depth,
+    };
+
+    let scope = Plot::default()
+        .block(block)
+        .marker(pane.marker)
+        .x_bounds([0., duration.max(step)])
+        .y_bounds([0., 1.])
+        .paint(|painter| {
+            painter.line(0., 0.5, duration, 0.5, theme.muted);
+            DisplayStrategyFactory::get_display_strategy(&pane.display_mode)
+                .render(painter, &levels, step, &palette);
+        });
+    f.render_widget(scope, area);
+}
+
+// the difference mode shows this many dB above and below the reference
+const DIFFERENCE_RANGE_DB: f64 = 30.;
+
+// connects neighbouring levels, already normalized to [0, 1], with lines
+fn draw_trace(painter: &mut Painter, levels: &[f64], freq_step: f64, color: Color) {
+    for (i, pair) in levels.windows(2).enumerate() {
+        painter.line(
+            freq_step * i as f64,
+            pair[0],
+            freq_step * (i + 1) as f64,
+            pair[1],
+            color,
+        );
+    }
+}
+
+fn curve_color(curve: &Curve, theme: &Theme) -> Color {
+    match curve.kind {
+        CurveKind::Reference => theme.reference,
+        CurveKind::UpperMask | CurveKind::LowerMask => theme.mask,
+    }
+}
+
+// sampled across the view rather than connecting the points directly, since the curve is
+// interpolated on a logarithmic axis but drawn on a linear one
+fn draw_curve(painter: &mut Painter, app: &App, curve: &Curve) {
+    const STEPS: usize = 256;
+
+    let (Some(first), Some(last)) = (curve.points.first(), curve.points.last()) else {
+        return;
+    };
+    let (low, high) = app.freq_view;
+    let (start, end) = (first.0.max(low), last.0.min(high));
+    if start >= end {
+        return;
+    }
+
+    let points: Vec<(f64, f64)> = (0..=STEPS)
+        .map(|i| start + (end - start) * i as f64 / STEPS as f64)
+        .filter_map(|freq| Some((freq, app.normalize_db(curve.level_at(freq)?))))
+        .collect();
+    for pair in points.windows(2) {
+        painter.line(
+            pair[0].0,
+            pair[0].1,
+            pair[1].0,
+            pair[1].1,
+            curve_color(curve, app.theme()),
+        );
+    }
+}
+
+// the frequency at the given column of the spectrum, if it is inside of it
+pub fn freq_at(app: &App, area: Rect, column: u16) -> Option<f64> {
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    if !(inner.left()..inner.right()).contains(&column) {
+        return None;
+    }
+    let (low, high) = app.freq_view;
+    let position = (f64::from(column - inner.x) + 0.5) / f64::from(inner.width);
+    Some(low + (high - low) * position)
+}
+
+fn format_freq(freq: f64) -> String {
+    if freq >= 1000. {
+        format!("{:.2} kHz", freq / 1000.)
+    } else {
+        format!("{freq:.0} Hz")
+    }
+}
+
+fn render_settings(f: &mut Frame, app: &App, size: Rect) {
+    let lines: Vec<Line> = Setting::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, setting)| {
+            let line = Line::raw(format!(
+                "{:<16}{}",
+                setting.label(),
+                app.setting_value(*setting)
+            ));
+            if i == app.settings_idx {
+                line.style(Style::default().bg(app.theme().highlight).fg(Color::Black))
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    let area = centered_rect(50, 40, size);
+    let panel = Paragraph::new(lines)
+        .block(Block::default().title(" Settings ").borders(Borders::ALL))
+        .style(Style::default().bg(app.theme().muted));
+    f.render_widget(Clear, area);
+    f.render_widget(panel, area);
+}
+
+// in the top right corner, below the title
+fn render_perf(f: &mut Frame, app: &App, size: Rect) {
+    let perf = &app.perf;
+    let ms = |duration: std::time::Duration| duration.as_secs_f64() * 1000.;
+    let timing = |label: &str, timing: &crate::perf::Timing| {
+        Line::raw(format!(
+            "{label:<10}{:>6.2} ms avg {:>7.2} max",
+            ms(timing.mean()),
+            ms(timing.max())
+        ))
+    };
+    let lines = vec![
+        Line::raw(format!(
+            "{:<10}{:>6.1} (target {})",
+            "FPS",
+            perf.fps(),
+            app.args.fps
+        )),
+        timing("Frame", &perf.frame),
+        timing("Render", &perf.render),
+        timing("FFT", &perf.fft),
+        timing("Callback", &perf.callback),
+        Line::raw(format!(
+            "{:<10}{:>6.0} %",
+            "Buffer",
+            perf.buffer_fill * 100.
+        )),
+        Line::styled(
+            format!(
+                "{:<10}{:>6} samples in {} xruns",
+                "Dropped", perf.xruns.dropped_samples, perf.xruns.xruns
+            ),
+            if perf.xruns.xruns > 0 {
+                Style::default().fg(app.theme().warning)
+            } else {
+                Style::default()
+            },
+        ),
+    ];
+
+    let width = 38.min(size.width);
+    let height = (lines.len() as u16 + 2).min(size.height.saturating_sub(1));
+    let area = Rect::new(
+        size.right().saturating_sub(width + 1),
+        size.y + 1,
+        width,
+        height,
+    );
+    let panel = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(" Performance ")
+                .borders(Borders::ALL),
+        )
+        .style(Style::default().bg(app.theme().muted));
+    f.render_widget(Clear, area);
+    f.render_widget(panel, area);
+}
+
+// lists the current bindings, so rebinding a key in the config shows up here as well
+fn render_help(f: &mut Frame, app: &App, size: Rect) {
+    let lines: Vec<Line> = app
+        .keymap
+        .bindings()
+        .iter()
+        .map(|(action, keys)| {
+            let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<24}", keys.join(" ")),
+                    Style::default().fg(app.theme().highlight),
+                ),
+                Span::raw(action.description()),
+            ])
+        })
+        .collect();
+
+    let area = help_area(size);
+    let scroll = app.help_scroll.min(max_help_scroll(app, size));
+    let shown = lines.len().min(scroll + help_rows(size));
+    let help = Paragraph::new(lines).scroll((scroll as u16, 0)).block(
+        Block::default().borders(Borders::ALL).title(format!(
+            " Key bindings {}-{shown} of {} - {}/{} to scroll, {} or Esc to close ",
+            scroll + 1,
+            app.keymap.bindings().len(),
+            first_key(app, Action::Previous),
+            first_key(app, Action::Next),
+            first_key(app, Action::Help),
+        )),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+}
+
+fn help_area(size: Rect) -> Rect {
+    centered_rect(60, 60, size)
+}
+
+// how many bindings fit into the help at once
+pub fn help_rows(size: Rect) -> usize {
+    usize::from(help_area(size).height.saturating_sub(2))
+}
+
+// the help scrolls no further than to show the last binding on its last row
+pub fn max_help_scroll(app: &App, size: Rect) -> usize {
+    app.keymap.bindings().len().saturating_sub(help_rows(size))
+}
+
+fn first_key(app: &App, action: Action) -> String {
+    app.keymap
+        .bindings()
+        .iter()
+        .find(|(a, _)| *a == action)
+        .and_then(|(_, keys)| keys.first())
+        .map_or_else(|| "-".into(), |key| key.to_string())
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     // Cut the given rectangle into three vertical pieces
     let popup_layout = Layout::default()
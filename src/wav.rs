@@ -0,0 +1,83 @@
+use std::{
+    io::{Seek, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+pub struct WavData {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+// reads a whole wav file and mixes all channels down to a single mono channel
+pub fn read_mono(path: &Path) -> Result<WavData, hound::Error> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let channels = usize::from(spec.channels);
+
+    let interleaved = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let samples = interleaved
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok(WavData {
+        samples,
+        sample_rate: spec.sample_rate,
+    })
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum RecordFormat {
+    /// 32-bit float
+    F32,
+    /// 16-bit PCM
+    I16,
+    /// 24-bit PCM
+    I24,
+}
+
+pub fn spec(format: RecordFormat, sample_rate: u32) -> WavSpec {
+    let (bits_per_sample, sample_format) = match format {
+        RecordFormat::F32 => (32, SampleFormat::Float),
+        RecordFormat::I16 => (16, SampleFormat::Int),
+        RecordFormat::I24 => (24, SampleFormat::Int),
+    };
+    WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample,
+        sample_format,
+    }
+}
+
+pub fn write_samples<W: Write + Seek>(
+    writer: &mut WavWriter<W>,
+    format: RecordFormat,
+    samples: &[f32],
+) -> Result<(), hound::Error> {
+    for sample in samples {
+        match format {
+            RecordFormat::F32 => writer.write_sample(*sample)?,
+            RecordFormat::I16 => {
+                writer.write_sample((sample.clamp(-1., 1.) * f32::from(i16::MAX)) as i16)?
+            }
+            RecordFormat::I24 => {
+                writer.write_sample((sample.clamp(-1., 1.) * 8_388_607.) as i32)?
+            }
+        }
+    }
+    Ok(())
+}
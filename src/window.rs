@@ -0,0 +1,623 @@
+//! Window functions applied to blocks of samples before they are transformed, and the level
+//! corrections that make spectra taken with different windows comparable.
+
+use std::{
+    f64::consts::PI,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use apodize::CosineWindowIter;
+use realfft::{num_complex::Complex, RealFftPlanner};
+
+/// Window function applied to each block of samples before the FFT.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowType {
+    /// Hann window.
+    Hanning,
+    /// Hamming window.
+    Hamming,
+    /// Blackman window, with sidelobes below -58 dB.
+    Blackman,
+    /// Nuttall window.
+    Nuttall,
+    /// 4-term Blackman-Harris window, with sidelobes below -92 dB.
+    BlackmanHarris,
+    /// Flat-top window, whose flat main lobe reads the amplitude of a tone within 0.1 dB
+    /// wherever it falls between two bins.
+    FlatTop,
+    /// Kaiser window with the given β, larger values trade a wider main lobe for lower
+    /// sidelobes.
+    Kaiser(f64),
+    /// Tukey (tapered cosine) window with the given α in `[0, 1]`, the fraction of the window
+    /// inside the cosine tapers: 0 is rectangular and 1 is Hann.
+    Tukey(f64),
+    /// Gaussian window with the given standard deviation σ, relative to half the window
+    /// length.
+    Gaussian(f64),
+    /// Dolph-Chebyshev window with all sidelobes at the given attenuation in dB.
+    DolphChebyshev(f64),
+    /// Rectangular window, i.e. no window at all.
+    Rectangular,
+}
+
+impl WindowType {
+    /// Every window, the parameterized ones with their default parameter, in the order they
+    /// are cycled through.
+    pub const ALL: [WindowType; 11] = [
+        WindowType::Hanning,
+        WindowType::Hamming,
+        WindowType::Blackman,
+        WindowType::Nuttall,
+        WindowType::BlackmanHarris,
+        WindowType::FlatTop,
+        WindowType::Kaiser(8.6),
+        WindowType::Tukey(0.5),
+        WindowType::Gaussian(0.4),
+        WindowType::DolphChebyshev(100.),
+        WindowType::Rectangular,
+    ];
+
+    /// Returns the name of the window without its parameter, as accepted by
+    /// [`from_str`](WindowType::from_str).
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowType::Hanning => "hann",
+            WindowType::Hamming => "hamming",
+            WindowType::Blackman => "blackman",
+            WindowType::Nuttall => "nuttall",
+            WindowType::BlackmanHarris => "blackman-harris",
+            WindowType::FlatTop => "flat-top",
+            WindowType::Kaiser(_) => "kaiser",
+            WindowType::Tukey(_) => "tukey",
+            WindowType::Gaussian(_) => "gaussian",
+            WindowType::DolphChebyshev(_) => "chebyshev",
+            WindowType::Rectangular => "rectangular",
+        }
+    }
+
+    /// Returns the parameter of a parameterized window.
+    pub fn parameter(&self) -> Option<f64> {
+        match self {
+            WindowType::Kaiser(p)
+            | WindowType::Tukey(p)
+            | WindowType::Gaussian(p)
+            | WindowType::DolphChebyshev(p) => Some(*p),
+            _ => None,
+        }
+    }
+
+    /// Returns the `len` coefficients of the window, symmetric and at most 1.
+    pub fn coefficients(&self, len: usize) -> Vec<f64> {
+        if len <= 1 {
+            return vec![1.; len];
+        }
+        let window_fn: CosineWindowIter = match self {
+            WindowType::Hanning => apodize::hanning_iter(len),
+            WindowType::Hamming => apodize::hamming_iter(len),
+            WindowType::Nuttall => apodize::nuttall_iter(len),
+            // apodize's blackman is the 4-term Blackman-Harris window
+            WindowType::BlackmanHarris => apodize::blackman_iter(len),
+            WindowType::Blackman => return cosine_sum(&[0.42, 0.5, 0.08], len),
+            WindowType::FlatTop => {
+                return cosine_sum(
+                    &[
+                        0.21557895,
+                        0.41663158,
+                        0.277263158,
+                        0.083578947,
+                        0.006947368,
+                    ],
+                    len,
+                )
+            }
+            WindowType::Kaiser(beta) => return kaiser(*beta, len),
+            WindowType::Tukey(alpha) => return tukey(*alpha, len),
+            WindowType::Gaussian(sigma) => return gaussian(*sigma, len),
+            WindowType::DolphChebyshev(attenuation) => return chebyshev(*attenuation, len),
+            WindowType::Rectangular => return vec![1.; len],
+        };
+        window_fn.collect()
+    }
+
+    /// Returns the width of the main lobe between its first nulls, in bins of an FFT as long
+    /// as the window. Two tones closer than about half of it merge into one peak, however
+    /// finely the spectrum is sampled.
+    pub fn main_lobe_bins(&self) -> f64 {
+        // long enough for the width to settle, short enough to evaluate on every change
+        const LEN: usize = 128;
+        const STEPS_PER_BIN: usize = 16;
+        const MAX_BINS: usize = 16;
+
+        let coefficients = self.coefficients(LEN);
+        let response = |bins: f64| {
+            coefficients
+                .iter()
+                .enumerate()
+                .map(|(n, w)| Complex::from_polar(*w, -2. * PI * bins * n as f64 / LEN as f64))
+                .sum::<Complex<f64>>()
+                .norm()
+        };
+        // the first null is where the response stops falling, once it is well below the peak
+        // since the top of a flat-top window ripples
+        let peak = response(0.);
+        let mut previous = peak;
+        for step in 1..=MAX_BINS * STEPS_PER_BIN {
+            let current = response(step as f64 / STEPS_PER_BIN as f64);
+            if current > previous && previous < peak / 2. {
+                return 2. * (step - 1) as f64 / STEPS_PER_BIN as f64;
+            }
+            previous = current;
+        }
+        2. * MAX_BINS as f64
+    }
+}
+
+impl Display for WindowType {
+    /// Formats the window the way [`from_str`](WindowType::from_str) parses it, e.g.
+    /// `kaiser:8.6`.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.parameter() {
+            Some(parameter) => write!(f, "{}:{parameter}", self.name()),
+            None => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+/// The largest Kaiser beta [`WindowType::from_str`] accepts, about 370 dB of sidelobe
+/// attenuation.
+pub const MAX_KAISER_BETA: f64 = 40.;
+
+/// The largest Dolph-Chebyshev attenuation in dB [`WindowType::from_str`] accepts.
+pub const MAX_CHEBYSHEV_DB: f64 = 300.;
+
+impl FromStr for WindowType {
+    type Err = String;
+
+    /// Parses a window by name, ignoring case, e.g. `"blackman"`. `"hann"` is accepted as
+    /// well as `"hanning"`. Parameterized windows take their parameter after a colon, e.g.
+    /// `"kaiser:6"`, `"tukey:0.25"`, `"gaussian:0.3"` or `"chebyshev:80"`, and fall back to
+    /// the one in [`ALL`](WindowType::ALL) without it.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => {
+                let parameter = parameter
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid window parameter: {s}"))?;
+                (name, Some(parameter))
+            }
+            None => (s, None),
+        };
+        let window = match name.trim().to_ascii_lowercase().as_str() {
+            "hann" | "hanning" => WindowType::Hanning,
+            "hamming" => WindowType::Hamming,
+            "blackman" => WindowType::Blackman,
+            "nuttall" => WindowType::Nuttall,
+            "blackman-harris" | "blackmanharris" => WindowType::BlackmanHarris,
+            "flat-top" | "flattop" => WindowType::FlatTop,
+            "kaiser" => WindowType::Kaiser(parameter.unwrap_or(8.6)),
+            "tukey" => WindowType::Tukey(parameter.unwrap_or(0.5)),
+            "gaussian" => WindowType::Gaussian(parameter.unwrap_or(0.4)),
+            "chebyshev" | "dolph-chebyshev" => {
+                WindowType::DolphChebyshev(parameter.unwrap_or(100.))
+            }
+            "rectangular" | "rect" | "none" => WindowType::Rectangular,
+            _ => return Err(format!("unknown window: {s}")),
+        };
+
+        // sidelobes beyond what these reach are below the precision of an f64
+        let (valid, expected) = match window {
+            _ if parameter.is_none() => (true, String::new()),
+            WindowType::Kaiser(beta) => (
+                (0. ..=MAX_KAISER_BETA).contains(&beta),
+                format!("a beta from 0 to {MAX_KAISER_BETA}"),
+            ),
+            WindowType::Tukey(alpha) => {
+                ((0. ..=1.).contains(&alpha), "an alpha from 0 to 1".into())
+            }
+            WindowType::Gaussian(sigma) => {
+                (sigma > 0. && sigma.is_finite(), "a sigma above 0".into())
+            }
+            WindowType::DolphChebyshev(attenuation) => (
+                attenuation > 0. && attenuation <= MAX_CHEBYSHEV_DB,
+                format!("an attenuation above 0 and up to {MAX_CHEBYSHEV_DB} dB"),
+            ),
+            _ => return Err(format!("the {} window takes no parameter", window.name())),
+        };
+        if !valid {
+            return Err(format!(
+                "invalid window parameter: {s}, the {} window takes {expected}",
+                window.name()
+            ));
+        }
+        Ok(window)
+    }
+}
+
+/// What the levels of a windowed spectrum are corrected for. Every window attenuates the
+/// block, tones by its coherent gain and broadband noise by that and its equivalent noise
+/// bandwidth as well, so one correction can't fit both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Correction {
+    /// Levels are left attenuated by the window, so they differ from window to window.
+    None,
+    /// A tone reads the same level with every window, divided by the coherent gain.
+    Amplitude,
+    /// Broadband noise reads the same level per bin with every window, divided by the coherent
+    /// gain and the square root of the equivalent noise bandwidth.
+    Noise,
+}
+
+impl Correction {
+    /// Returns what magnitudes of a block windowed with `coefficients` are divided by.
+    pub fn gain(&self, coefficients: &[f64]) -> f64 {
+        let gain = coherent_gain(coefficients);
+        match self {
+            Correction::None => 1.,
+            Correction::Amplitude => gain,
+            Correction::Noise => gain * enbw(coefficients).sqrt(),
+        }
+    }
+}
+
+impl FromStr for Correction {
+    type Err = String;
+
+    /// Parses `"none"`, `"amplitude"` or `"noise"`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Correction::None),
+            "amplitude" => Ok(Correction::Amplitude),
+            "noise" => Ok(Correction::Noise),
+            _ => Err(format!("unknown correction: {s}")),
+        }
+    }
+}
+
+/// Returns the mean of the coefficients, which is how much a window attenuates a tone.
+pub fn coherent_gain(coefficients: &[f64]) -> f64 {
+    coefficients.iter().sum::<f64>() / coefficients.len().max(1) as f64
+}
+
+/// Returns the equivalent noise bandwidth of the window in bins, the width of a rectangular
+/// filter that lets through as much noise as one bin does.
+pub fn enbw(coefficients: &[f64]) -> f64 {
+    let sum = coefficients.iter().sum::<f64>();
+    let sum_sq = coefficients.iter().map(|w| w * w).sum::<f64>();
+    coefficients.len() as f64 * sum_sq / (sum * sum)
+}
+
+// a0 - a1 cos(2πx) + a2 cos(4πx) - ..., with x running from 0 to 1 over the window
+fn cosine_sum(terms: &[f64], len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|n| {
+            let x = 2. * PI * n as f64 / (len - 1) as f64;
+            terms
+                .iter()
+                .enumerate()
+                .map(|(k, a)| {
+                    let sign = if k.is_multiple_of(2) { 1. } else { -1. };
+                    sign * a * (k as f64 * x).cos()
+                })
+                .sum()
+        })
+        .collect()
+}
+
+fn kaiser(beta: f64, len: usize) -> Vec<f64> {
+    let scale = bessel_i0(beta);
+    (0..len)
+        .map(|n| {
+            let x = 2. * n as f64 / (len - 1) as f64 - 1.;
+            bessel_i0(beta * (1. - x * x).max(0.).sqrt()) / scale
+        })
+        .collect()
+}
+
+// the modified Bessel function of the first kind and order zero, from its power series
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.;
+    let mut term = 1.;
+    let mut k = 1.;
+    while term > sum * 1e-12 {
+        term *= (x / (2. * k)).powi(2);
+        sum += term;
+        k += 1.;
+    }
+    sum
+}
+
+fn tukey(alpha: f64, len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|n| {
+            let x = n as f64 / (len - 1) as f64;
+            let edge = x.min(1. - x);
+            if alpha <= 0. || edge >= alpha / 2. {
+                1.
+            } else {
+                0.5 * (1. - (2. * PI * edge / alpha).cos())
+            }
+        })
+        .collect()
+}
+
+fn gaussian(sigma: f64, len: usize) -> Vec<f64> {
+    let half = (len - 1) as f64 / 2.;
+    (0..len)
+        .map(|n| (-0.5 * ((n as f64 - half) / (sigma * half)).powi(2)).exp())
+        .collect()
+}
+
+// computed from its spectrum, a Chebyshev polynomial of the window's order sampled around the
+// unit circle, which ripples evenly at the attenuation outside of the main lobe
+fn chebyshev(attenuation: f64, len: usize) -> Vec<f64> {
+    let odd = !len.is_multiple_of(2);
+    let order = (len - 1) as f64;
+    let ratio = 10f64.powf(attenuation / 20.);
+    let x0 = (ratio.acosh() / order).cosh();
+    let polynomial = |x: f64| {
+        if x > 1. {
+            (order * x.acosh()).cosh()
+        } else if x < -1. {
+            let sign = if odd { 1. } else { -1. };
+            sign * (order * (-x).acosh()).cosh()
+        } else {
+            (order * x.acos()).cos()
+        }
+    };
+
+    // the transform of a real window only needs the first half of its spectrum; an even
+    // length is shifted by half a sample so the window comes out symmetric
+    let mut planner = RealFftPlanner::<f64>::new();
+    let c2r = planner.plan_fft_inverse(len);
+    let mut spectrum = c2r.make_input_vec();
+    for (k, value) in spectrum.iter_mut().enumerate() {
+        let p = polynomial(x0 * (PI * k as f64 / len as f64).cos());
+        // conjugated, so the inverse transform gives the forward one
+        *value = if odd {
+            Complex::new(p, 0.)
+        } else {
+            Complex::from_polar(p, -PI * k as f64 / len as f64)
+        };
+    }
+    // these have to be real for the inverse of a real signal, rounding leaves them close to it
+    spectrum[0].im = 0.;
+    if !odd {
+        spectrum[len / 2].im = 0.;
+    }
+    let mut output = c2r.make_output_vec();
+    if c2r.process(&mut spectrum, &mut output).is_err() {
+        return vec![1.; len];
+    }
+
+    // the output starts at the center of the window and wraps around, an odd length has a
+    // sample right at the center and an even one two samples either side of it
+    let half = len.div_ceil(2);
+    let window: Vec<f64> = if odd {
+        output[1..half]
+            .iter()
+            .rev()
+            .chain(&output[..half])
+            .copied()
+            .collect()
+    } else {
+        output[1..=half]
+            .iter()
+            .rev()
+            .chain(&output[1..=half])
+            .copied()
+            .collect()
+    };
+    let max = window.iter().copied().fold(f64::MIN, f64::max);
+    window.iter().map(|w| w / max).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the magnitude response in dB relative to its peak, `oversample` points per bin from 0
+    // up to half the window length
+    fn response_db(coefficients: &[f64], oversample: usize) -> Vec<f64> {
+        let len = coefficients.len() as f64;
+        let response: Vec<f64> = (0..coefficients.len() / 2 * oversample)
+            .map(|i| {
+                let bins = i as f64 / oversample as f64;
+                coefficients
+                    .iter()
+                    .enumerate()
+                    .map(|(n, w)| Complex::from_polar(*w, -2. * PI * bins * n as f64 / len))
+                    .sum::<Complex<f64>>()
+                    .norm()
+            })
+            .collect();
+        response
+            .iter()
+            .map(|r| 20. * (r / response[0]).log10())
+            .collect()
+    }
+
+    #[test]
+    fn windows_are_symmetric_with_peak_one() {
+        for window in WindowType::ALL {
+            for len in [64, 65] {
+                let coefficients = window.coefficients(len);
+                assert_eq!(coefficients.len(), len);
+                for (a, b) in coefficients.iter().zip(coefficients.iter().rev()) {
+                    assert!((a - b).abs() < 1e-9, "{window} of {len} is not symmetric");
+                }
+                let peak = coefficients.iter().copied().fold(f64::MIN, f64::max);
+                // the published flat-top coefficients are rounded and add up to a bit over 1
+                assert!(peak <= 1. + 1e-6, "{window} of {len} peaks at {peak}");
+                // an odd length has a sample right at the center, where every window is 1
+                if len % 2 == 1 {
+                    assert!(
+                        (peak - 1.).abs() < 1e-6,
+                        "{window} of {len} peaks at {peak}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn short_windows() {
+        assert!(WindowType::Hanning.coefficients(0).is_empty());
+        assert_eq!(WindowType::Kaiser(8.6).coefficients(1), vec![1.]);
+    }
+
+    #[test]
+    fn chebyshev_sidelobes_are_at_the_attenuation() {
+        for attenuation in [40., 60., 80., 100.] {
+            for len in [64, 65] {
+                let response = response_db(
+                    &WindowType::DolphChebyshev(attenuation).coefficients(len),
+                    32,
+                );
+                let null = response
+                    .windows(2)
+                    .position(|pair| pair[1] > pair[0])
+                    .unwrap();
+                let sidelobe = response[null..].iter().copied().fold(f64::MIN, f64::max);
+                assert!(
+                    (sidelobe + attenuation).abs() < 0.5,
+                    "chebyshev:{attenuation} of {len} has sidelobes at {sidelobe:.2} dB"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn main_lobe_widths() {
+        let cases = [
+            (WindowType::Rectangular, 2.),
+            (WindowType::Hanning, 4.),
+            (WindowType::Hamming, 4.),
+            (WindowType::Blackman, 6.),
+            (WindowType::BlackmanHarris, 8.),
+            (WindowType::FlatTop, 10.),
+            // without a taper Kaiser and Tukey are rectangular
+            (WindowType::Kaiser(0.), 2.),
+            (WindowType::Tukey(0.), 2.),
+        ];
+        for (window, bins) in cases {
+            let width = window.main_lobe_bins();
+            assert!((width - bins).abs() <= 0.3, "{window}: {width} bins");
+        }
+    }
+
+    #[test]
+    fn corrections() {
+        let rectangular = WindowType::Rectangular.coefficients(1024);
+        let hann = WindowType::Hanning.coefficients(1024);
+        assert!((enbw(&rectangular) - 1.).abs() < 1e-9);
+        assert!((enbw(&hann) - 1.5).abs() < 0.01);
+        assert!((coherent_gain(&hann) - 0.5).abs() < 0.01);
+
+        assert_eq!(Correction::None.gain(&hann), 1.);
+        assert_eq!(Correction::Amplitude.gain(&hann), coherent_gain(&hann));
+        let noise = coherent_gain(&hann) * enbw(&hann).sqrt();
+        assert!((Correction::Noise.gain(&hann) - noise).abs() < 1e-12);
+        assert_eq!(Correction::Noise.gain(&rectangular), 1.);
+    }
+
+    #[test]
+    fn parse_windows() {
+        let cases = [
+            ("hann", WindowType::Hanning),
+            ("Hanning", WindowType::Hanning),
+            ("BLACKMAN", WindowType::Blackman),
+            ("blackmanharris", WindowType::BlackmanHarris),
+            ("flattop", WindowType::FlatTop),
+            ("kaiser", WindowType::Kaiser(8.6)),
+            ("kaiser:6", WindowType::Kaiser(6.)),
+            ("tukey: 0.25", WindowType::Tukey(0.25)),
+            ("gaussian:0.3", WindowType::Gaussian(0.3)),
+            ("dolph-chebyshev:80", WindowType::DolphChebyshev(80.)),
+            ("none", WindowType::Rectangular),
+        ];
+        for (s, window) in cases {
+            assert_eq!(s.parse::<WindowType>(), Ok(window), "{s}");
+        }
+    }
+
+    #[test]
+    fn parse_invalid_windows() {
+        let cases = [
+            ("", "unknown window: "),
+            ("welch", "unknown window: welch"),
+            ("kaiser:x", "invalid window parameter: kaiser:x"),
+            (
+                "kaiser:-1",
+                "invalid window parameter: kaiser:-1, the kaiser window takes a beta from 0 to 40",
+            ),
+            (
+                "tukey:2",
+                "invalid window parameter: tukey:2, the tukey window takes an alpha from 0 to 1",
+            ),
+            (
+                "gaussian:0",
+                "invalid window parameter: gaussian:0, the gaussian window takes a sigma above 0",
+            ),
+            ("hann:3", "the hann window takes no parameter"),
+        ];
+        for (s, error) in cases {
+            assert_eq!(s.parse::<WindowType>(), Err(error.to_string()), "{s}");
+        }
+    }
+
+    #[test]
+    fn parameters_out_of_range_are_rejected() {
+        assert_eq!("kaiser:40".parse(), Ok(WindowType::Kaiser(40.)));
+        assert_eq!(
+            "chebyshev:300".parse(),
+            Ok(WindowType::DolphChebyshev(300.))
+        );
+        for s in [
+            "kaiser:40.5",
+            "kaiser:inf",
+            "kaiser:NaN",
+            "chebyshev:301",
+            "chebyshev:inf",
+            "chebyshev:nan",
+            "chebyshev:0",
+            "gaussian:inf",
+            "tukey:nan",
+        ] {
+            assert!(s.parse::<WindowType>().is_err(), "{s} was accepted");
+        }
+    }
+
+    #[test]
+    fn windows_at_the_limits_are_finite() {
+        for window in [WindowType::Kaiser(40.), WindowType::DolphChebyshev(300.)] {
+            for len in [2, 511, 4096] {
+                let coefficients = window.coefficients(len);
+                assert!(
+                    coefficients.iter().all(|c| c.is_finite()),
+                    "{window} of {len}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn windows_round_trip() {
+        for window in WindowType::ALL {
+            assert_eq!(window.to_string().parse::<WindowType>(), Ok(window.clone()));
+        }
+        assert_eq!(WindowType::Tukey(0.25).to_string(), "tukey:0.25");
+    }
+
+    #[test]
+    fn parse_corrections() {
+        assert_eq!("none".parse(), Ok(Correction::None));
+        assert_eq!("Amplitude".parse(), Ok(Correction::Amplitude));
+        assert_eq!("noise".parse(), Ok(Correction::Noise));
+        assert_eq!(
+            "power".parse::<Correction>(),
+            Err("unknown correction: power".to_string())
+        );
+    }
+}